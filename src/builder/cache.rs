@@ -14,11 +14,15 @@ impl Cache {
     }
 
     pub fn get_cache_file_path(&self, key: &str) -> String {
+        self.get_cache_file_path_with_extension(key, "txt")
+    }
+
+    fn get_cache_file_path_with_extension(&self, key: &str, extension: &str) -> String {
         let mut hasher = Md5::new();
         hasher.update(key);
         let key = hasher.finalize();
         let key = format!("{:x}", key);
-        format!("{}/{}.txt", self.cache_dir, key)
+        format!("{}/{}.{}", self.cache_dir, key, extension)
     }
 
     pub fn set(&self, key: &str, value: &str) -> Result<()> {
@@ -39,6 +43,23 @@ impl Cache {
         fs::read_to_string(file_path).ok()
     }
 
+    pub fn set_bytes(&self, key: &str, value: &[u8]) -> Result<()> {
+        let file_path = self.get_cache_file_path_with_extension(key, "bin");
+
+        if let Some(parent_dir) = Path::new(&file_path).parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+
+        fs::write(file_path, value)?;
+
+        Ok(())
+    }
+
+    pub fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        let file_path = self.get_cache_file_path_with_extension(key, "bin");
+        fs::read(file_path).ok()
+    }
+
     pub fn clean(&self) -> Result<()> {
         fs::remove_dir_all(&self.cache_dir)?;
         Ok(())
@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde_yaml::Value;
+
+use super::front_matter::{Format, FrontMatter};
+use crate::shared::utils;
+
+pub const DEFAULTS_FILE: &str = "_defaults.yaml";
+
+pub fn is_defaults_file(file: &str) -> bool {
+    Path::new(file)
+        .file_name()
+        .is_some_and(|x| x == DEFAULTS_FILE)
+}
+
+// Contents of each `_defaults.yaml` file, by directory
+pub fn read_defaults_files(files: &[String], input_dir: &str) -> Result<HashMap<PathBuf, Value>> {
+    files
+        .iter()
+        .filter(|file| is_defaults_file(file))
+        .map(|file| {
+            let name = utils::get_source_name(file, input_dir);
+            let content = fs::read_to_string(file)
+                .with_context(|| format!("Failed to read defaults file: {}", name))?;
+            let defaults = FrontMatter {
+                format: Format::Yaml,
+                source: content,
+                line: 1,
+            }
+            .parse(&name)?;
+            let defaults = check_mapping(defaults, &name, "defaults")?;
+
+            Ok((get_directory(file), defaults))
+        })
+        .collect()
+}
+
+// `cascade` front matter of a folder page, applied to the pages below it
+pub fn get_cascade(
+    file: &str,
+    input_dir: &str,
+    metadata: &Value,
+) -> Result<Option<(PathBuf, Value)>> {
+    match metadata.get("cascade") {
+        Some(cascade) => Ok(Some((
+            get_directory(file),
+            check_mapping(
+                cascade.clone(),
+                &utils::get_source_name(file, input_dir),
+                "cascade",
+            )?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+// Defaults of a page, merged from the pages directory down to the page's own
+// directory so that deeper directories take precedence
+pub fn get_page_defaults(
+    pages_dir: &str,
+    file: &str,
+    defaults: &HashMap<PathBuf, Value>,
+    cascades: &HashMap<PathBuf, Value>,
+) -> Value {
+    let mut directories = Path::new(file)
+        .ancestors()
+        .skip(1)
+        .take_while(|x| x.starts_with(pages_dir))
+        .map(|x| x.to_path_buf())
+        .collect::<Vec<PathBuf>>();
+    directories.reverse();
+
+    let is_folder_page = file.ends_with("page.md");
+    let own_directory = get_directory(file);
+
+    directories
+        .iter()
+        .flat_map(|directory| {
+            let cascade = cascades
+                .get(directory)
+                .filter(|_| !is_folder_page || *directory != own_directory);
+            [defaults.get(directory), cascade]
+        })
+        .flatten()
+        .filter(|x| !x.is_null())
+        .fold(Value::Null, |result, x| {
+            utils::merge_yaml_values(result, x.clone())
+        })
+}
+
+fn get_directory(file: &str) -> PathBuf {
+    Path::new(file)
+        .parent()
+        .map(|x| x.to_path_buf())
+        .unwrap_or_default()
+}
+
+fn check_mapping(value: Value, file: &str, name: &str) -> Result<Value> {
+    match value {
+        Value::Mapping(_) | Value::Null => Ok(value),
+        _ => Err(anyhow!(
+            "Invalid {} in {}, expected a mapping of front matter values",
+            name,
+            file
+        )),
+    }
+}
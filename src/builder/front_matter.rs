@@ -0,0 +1,267 @@
+use std::fmt;
+
+use anyhow::Result;
+use serde_yaml::Value;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    fn name(&self) -> &'static str {
+        match self {
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+            Format::Json => "JSON",
+        }
+    }
+}
+
+// Parse error pointing at the line and column of the file
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub format: Format,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    pub snippet: Option<String>,
+}
+
+impl Diagnostic {
+    fn get_hint(&self) -> &'static str {
+        let hints: &[(&str, &str)] = match self.format {
+            Format::Yaml => &[
+                (
+                    "mapping values are not allowed",
+                    "quote values that contain `: `, like `title: \"Rust: a guide\"`",
+                ),
+                (
+                    "did not find expected ','",
+                    "a `[` list or `{` mapping on this line or before it isn't closed",
+                ),
+                (
+                    "found character that cannot start any token",
+                    "indent with spaces rather than tabs, and quote values starting with `@` or `` ` ``",
+                ),
+                (
+                    "did not find expected key",
+                    "check the indentation, keys of the same mapping have to line up",
+                ),
+                ("duplicate", "each key can only be set once"),
+            ],
+            Format::Toml => &[
+                ("duplicate", "each key can only be set once"),
+                (
+                    "invalid string",
+                    "text values need quotes, like `title = \"Hello\"`",
+                ),
+                (
+                    "expected newline",
+                    "each line holds a single `key = value`, text values need quotes",
+                ),
+            ],
+            Format::Json => &[
+                ("trailing comma", "remove the comma after the last entry"),
+                (
+                    "expected `,`",
+                    "entries are separated with commas, check the end of the previous line",
+                ),
+                (
+                    "key must be a string",
+                    "keys need double quotes, like `\"title\": \"Hello\"`",
+                ),
+            ],
+        };
+
+        hints
+            .iter()
+            .find(|(pattern, _)| self.message.contains(pattern))
+            .map(|(_, hint)| *hint)
+            .unwrap_or(match self.format {
+                Format::Yaml => "front matter between `---` lines has to be valid YAML",
+                Format::Toml => "front matter between `+++` lines has to be valid TOML",
+                Format::Json => {
+                    "JSON front matter has to be a valid object, closed by `}` on its own line"
+                }
+            })
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Invalid {} front matter at {}:{}:{}, {}",
+            self.format.name(),
+            self.file,
+            self.line,
+            self.column,
+            self.message
+        )?;
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        if let Some(snippet) = &self.snippet {
+            // Keep tabs so that the caret lines up with the snippet
+            let caret_offset = snippet
+                .chars()
+                .take(self.column.saturating_sub(1))
+                .map(|x| if x == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+
+            writeln!(f, "{} |", gutter)?;
+            writeln!(f, "{} | {}", self.line, snippet)?;
+            writeln!(f, "{} | {}^", gutter, caret_offset)?;
+        }
+
+        write!(f, "{} = hint: {}", gutter, self.get_hint())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+pub struct FrontMatter {
+    pub format: Format,
+    pub source: String,
+    // Line of the file the source starts on
+    pub line: usize,
+}
+
+impl FrontMatter {
+    // Front matter as a YAML value, whatever its format
+    pub fn parse(&self, file: &str) -> Result<Value> {
+        match self.format {
+            Format::Yaml => serde_yaml::from_str(&self.source).map_err(|e| {
+                let (line, column) = e
+                    .location()
+                    .map(|x| (x.line(), x.column()))
+                    .unwrap_or((1, 1));
+                self.error(file, line, column, &e.to_string()).into()
+            }),
+            Format::Toml => toml::from_str::<toml::Table>(&self.source)
+                .map(|x| toml_to_yaml(toml::Value::Table(x)))
+                .map_err(|e| {
+                    let (line, column) = e
+                        .span()
+                        .map(|x| get_position(&self.source, x.start))
+                        .unwrap_or((1, 1));
+                    self.error(file, line, column, e.message()).into()
+                }),
+            Format::Json => {
+                let value = serde_json::from_str::<serde_json::Value>(&self.source)
+                    .map_err(|e| self.error(file, e.line(), e.column(), &e.to_string()))?;
+                Ok(serde_yaml::to_value(value)?)
+            }
+        }
+    }
+
+    // Line of the file a key is first set on, whatever the format
+    pub fn get_key_line(&self, key: &str) -> Option<usize> {
+        let quoted = format!("\"{}\"", key);
+        self.source
+            .lines()
+            .position(|line| {
+                [key, quoted.as_str()].into_iter().any(|x| {
+                    line.trim_start()
+                        .strip_prefix(x)
+                        .is_some_and(|rest| rest.trim_start().starts_with([':', '=']))
+                })
+            })
+            .map(|x| self.line + x)
+    }
+
+    fn error(&self, file: &str, line: usize, column: usize, message: &str) -> Diagnostic {
+        // Parsers report positions within the front matter, not the file
+        let message = message.split(" at line ").next().unwrap_or(message);
+        let line = line.max(1);
+
+        Diagnostic {
+            file: file.to_string(),
+            format: self.format,
+            line: self.line + line - 1,
+            column,
+            message: message.to_string(),
+            snippet: self
+                .source
+                .lines()
+                .nth(line - 1)
+                .map(|x| x.trim_end().to_string()),
+        }
+    }
+}
+
+// Splits a markdown file into its front matter, the body and the line the body starts on.
+// `---` fences YAML, `+++` fences TOML and a leading `{` ... `}` object is JSON
+pub fn split(markdown: &str) -> (Option<FrontMatter>, &str, usize) {
+    let first_line_end = markdown.find('\n').map(|x| x + 1).unwrap_or(markdown.len());
+
+    let (format, closing) = match markdown[..first_line_end].trim_end() {
+        "---" => (Format::Yaml, "---"),
+        "+++" => (Format::Toml, "+++"),
+        "{" => (Format::Json, "}"),
+        _ => return (None, markdown, 1),
+    };
+
+    let mut offset = first_line_end;
+    while offset < markdown.len() {
+        let line_end = markdown[offset..]
+            .find('\n')
+            .map(|x| offset + x + 1)
+            .unwrap_or(markdown.len());
+
+        if markdown[offset..line_end].trim_end() == closing {
+            // JSON braces are part of the object
+            let front_matter = if format == Format::Json {
+                FrontMatter {
+                    format,
+                    source: markdown[..line_end].to_string(),
+                    line: 1,
+                }
+            } else {
+                FrontMatter {
+                    format,
+                    source: markdown[first_line_end..offset].to_string(),
+                    line: 2,
+                }
+            };
+            let first_line = markdown[..line_end].matches('\n').count() + 1;
+
+            return (Some(front_matter), &markdown[line_end..], first_line);
+        }
+
+        offset = line_end;
+    }
+
+    (None, markdown, 1)
+}
+
+// 1-based line and column of a byte offset
+fn get_position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map(|x| x + 1).unwrap_or(0);
+
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+pub fn toml_to_yaml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(x) => Value::String(x),
+        toml::Value::Integer(x) => x.into(),
+        toml::Value::Float(x) => x.into(),
+        toml::Value::Boolean(x) => x.into(),
+        // Dates are kept as text, like unquoted dates in YAML
+        toml::Value::Datetime(x) => Value::String(x.to_string()),
+        toml::Value::Array(x) => Value::Sequence(x.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(x) => Value::Mapping(
+            x.into_iter()
+                .map(|(key, value)| (Value::String(key), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::images::{get_dimensions, ImageProcessor};
+
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GalleryImage {
+    pub src: String,
+    pub thumbnail: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub date: Option<i64>,
+    pub camera: Option<String>,
+    pub caption: Option<String>,
+}
+
+pub fn get_gallery(
+    images: &ImageProcessor,
+    page_file: &str,
+    gallery_dir: &str,
+) -> Result<Vec<GalleryImage>> {
+    get_gallery_files(page_file, gallery_dir)?
+        .iter()
+        .map(|file| {
+            get_gallery_image(images, file)
+                .with_context(|| format!("Failed to add gallery image: {}", file.display()))
+        })
+        .collect()
+}
+
+// Images of the gallery folder, relative to the page
+pub fn get_gallery_files(page_file: &str, gallery_dir: &str) -> Result<Vec<PathBuf>> {
+    let gallery_dir = Path::new(page_file)
+        .parent()
+        .context("Failed to get parent folder")?
+        .join(gallery_dir);
+
+    let mut files = fs::read_dir(&gallery_dir)
+        .with_context(|| format!("Failed to read gallery folder: {}", gallery_dir.display()))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|x| {
+            x.is_file()
+                && x.extension()
+                    .map(|x| x.to_string_lossy().to_lowercase())
+                    .is_some_and(|x| IMAGE_EXTENSIONS.contains(&x.as_str()))
+        })
+        .collect::<Vec<PathBuf>>();
+    files.sort();
+
+    Ok(files)
+}
+
+fn get_gallery_image(images: &ImageProcessor, file: &Path) -> Result<GalleryImage> {
+    let exif = fs::File::open(file).ok().and_then(|x| {
+        exif::Reader::new()
+            .read_from_container(&mut BufReader::new(x))
+            .ok()
+    });
+    // Thumbnails are rotated upright, so are the dimensions
+    let dimensions = get_dimensions(file).ok();
+
+    let src = images.publish(file)?;
+    let thumbnail = images.thumbnail(file)?.unwrap_or_else(|| src.clone());
+
+    let (date, camera, caption) = match &exif {
+        Some(exif) => (get_date(exif), get_camera(exif), get_caption(exif)),
+        None => (None, None, None),
+    };
+
+    Ok(GalleryImage {
+        src,
+        thumbnail,
+        width: dimensions.map(|x| x.0),
+        height: dimensions.map(|x| x.1),
+        date,
+        camera,
+        caption,
+    })
+}
+
+fn get_string(exif: &exif::Exif, tag: exif::Tag) -> Option<String> {
+    match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+        exif::Value::Ascii(values) => values
+            .first()
+            .map(|x| String::from_utf8_lossy(x).trim().to_string())
+            .filter(|x| !x.is_empty()),
+        _ => None,
+    }
+}
+
+// Timestamp in milliseconds, same as the `date_published` convention used by `format-date`
+fn get_date(exif: &exif::Exif) -> Option<i64> {
+    let field = exif
+        .get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+        .or_else(|| exif.get_field(exif::Tag::DateTime, exif::In::PRIMARY))?;
+
+    let date = match &field.value {
+        exif::Value::Ascii(values) => exif::DateTime::from_ascii(values.first()?).ok()?,
+        _ => return None,
+    };
+
+    NaiveDate::from_ymd_opt(date.year.into(), date.month.into(), date.day.into())?
+        .and_hms_opt(date.hour.into(), date.minute.into(), date.second.into())
+        .map(|x| x.and_utc().timestamp_millis())
+}
+
+fn get_camera(exif: &exif::Exif) -> Option<String> {
+    let make = get_string(exif, exif::Tag::Make);
+    let model = get_string(exif, exif::Tag::Model);
+
+    match (make, model) {
+        // Most vendors repeat the make in the model name
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    }
+}
+
+fn get_caption(exif: &exif::Exif) -> Option<String> {
+    get_string(exif, exif::Tag::ImageDescription)
+}
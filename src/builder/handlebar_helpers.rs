@@ -5,6 +5,9 @@ use handlebars::{
 };
 use serde_json::{json, value::Value as Json, Map};
 
+use super::images::ImageProcessor;
+use crate::shared::settings::BuildSettings;
+
 #[derive(Clone, Copy)]
 pub struct SliceHelper;
 
@@ -192,3 +195,66 @@ impl HelperDef for SortByHelper {
         Ok(ScopedJson::Derived(Json::Object(sorted_object)))
     }
 }
+
+// Source file of the page being rendered, added to the data of every template
+pub const PAGE_FILE_KEY: &str = "__page_file";
+
+#[derive(Clone)]
+pub struct ResponsiveImageHelper {
+    pub images: ImageProcessor,
+}
+
+impl HelperDef for ResponsiveImageHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        ctx: &Context,
+        _: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let url = h
+            .param(0)
+            .and_then(|v| v.value().as_str())
+            .ok_or(RenderError::new("Image url must be a string"))?;
+        let file = ctx
+            .data()
+            .get(PAGE_FILE_KEY)
+            .and_then(|v| v.as_str())
+            .ok_or(RenderError::new("Failed to get the page being rendered"))?;
+        let alt = h
+            .hash_get("alt")
+            .and_then(|v| v.value().as_str())
+            .unwrap_or_default();
+        let title = h
+            .hash_get("title")
+            .and_then(|v| v.value().as_str())
+            .unwrap_or_default();
+
+        out.write(&self.images.render_image(file, url, alt, title))?;
+        Ok(())
+    }
+}
+
+pub struct UrlForHelper {
+    pub settings: BuildSettings,
+}
+
+impl HelperDef for UrlForHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper,
+        _: &Handlebars,
+        _: &Context,
+        _: &mut RenderContext,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let url = h
+            .param(0)
+            .and_then(|v| v.value().as_str())
+            .ok_or(RenderError::new("Url must be a string"))?;
+
+        out.write(&self.settings.prefix_url(url))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,501 @@
+use std::{
+    fs,
+    io::{BufRead, Cursor, Seek},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use image::{
+    codecs::{avif::AvifEncoder, jpeg::JpegEncoder, webp::WebPEncoder},
+    imageops::FilterType,
+    metadata::Orientation,
+    DynamicImage, ImageDecoder, ImageFormat, ImageReader,
+};
+use md5::{Digest, Md5};
+use pulldown_cmark::escape::{escape_href, escape_html};
+use slugify::slugify;
+
+use super::cache;
+use crate::shared::{
+    logger::Logger,
+    settings::{BuildSettings, ImageSettings},
+};
+
+pub const IMAGES_DIR: &str = "_images";
+
+#[derive(Debug, Clone)]
+pub struct ImageProcessor {
+    settings: ImageSettings,
+    // Used to prefix image urls with the base path
+    build_settings: BuildSettings,
+    public_dir: String,
+    output_dir: String,
+    cache: Option<cache::Cache>,
+}
+
+struct ImageSource {
+    mime_type: &'static str,
+    srcset: Vec<(String, u32)>,
+}
+
+struct SourceImage {
+    bytes: Vec<u8>,
+    format: ImageFormat,
+    hash: String,
+    stem: String,
+    // Size once the EXIF orientation is applied
+    width: u32,
+    height: u32,
+    orientation: Orientation,
+    decoded: Option<DynamicImage>,
+}
+
+pub struct ProcessedImage {
+    pub width: u32,
+    pub height: u32,
+    sources: Vec<ImageSource>,
+}
+
+impl ProcessedImage {
+    fn srcset(source: &ImageSource) -> String {
+        source
+            .srcset
+            .iter()
+            .map(|(url, width)| format!("{} {}w", url, width))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub fn to_picture_html(&self, alt: &str, title: &str) -> String {
+        let sizes = format!("(max-width: {0}px) 100vw, {0}px", self.width);
+        let mut html = String::from("<picture>");
+
+        // The last source is always the original format, used by the <img> fallback
+        let (fallback, modern) = match self.sources.split_last() {
+            Some(sources) => sources,
+            None => return html + "</picture>",
+        };
+
+        for source in modern {
+            html.push_str(&format!(
+                "<source type=\"{}\" srcset=\"{}\" sizes=\"{}\">",
+                source.mime_type,
+                Self::srcset(source),
+                sizes
+            ));
+        }
+
+        let src = fallback
+            .srcset
+            .last()
+            .map(|(url, _)| url.as_str())
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"",
+            src,
+            Self::srcset(fallback),
+            sizes,
+            self.width,
+            self.height
+        ));
+        let _ = escape_html(&mut html, alt);
+        if !title.is_empty() {
+            html.push_str("\" title=\"");
+            let _ = escape_html(&mut html, title);
+        }
+        html.push_str("\" loading=\"lazy\" decoding=\"async\"></picture>");
+
+        html
+    }
+}
+
+impl ImageProcessor {
+    pub fn new(
+        settings: ImageSettings,
+        build_settings: BuildSettings,
+        public_dir: &str,
+        output_dir: &str,
+        cache: Option<cache::Cache>,
+    ) -> Self {
+        Self {
+            settings,
+            build_settings,
+            public_dir: public_dir.to_string(),
+            output_dir: output_dir.to_string(),
+            cache,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.is_enabled()
+    }
+
+    pub fn resolve_source(&self, page_file: &str, url: &str) -> Option<PathBuf> {
+        if url.contains("://") || url.starts_with("//") || url.starts_with("data:") {
+            return None;
+        }
+
+        let url = url.split(['?', '#']).next().unwrap_or_default();
+
+        // Root-relative urls point to public files, others are page bundle assets
+        let path = if let Some(url) = url.strip_prefix('/') {
+            Path::new(&self.public_dir).join(url)
+        } else {
+            Path::new(page_file).parent()?.join(url)
+        };
+
+        path.is_file().then_some(path)
+    }
+
+    pub fn render_image(&self, page_file: &str, url: &str, alt: &str, title: &str) -> String {
+        if let Some(source) = self.resolve_source(page_file, url) {
+            match self.process(&source) {
+                Ok(Some(image)) => return image.to_picture_html(alt, title),
+                Ok(None) => {}
+                Err(e) => Logger::new().error(&format!(
+                    "Failed to process image {}: {}",
+                    source.display(),
+                    e
+                )),
+            }
+        }
+
+        render_plain_image(&self.build_settings.prefix_url(url), alt, title)
+    }
+
+    fn load(&self, source: &Path) -> Result<Option<SourceImage>> {
+        let bytes = fs::read(source)?;
+
+        // Other formats (e.g. GIF, SVG) are published as-is
+        let format = match image::guess_format(&bytes) {
+            Ok(format @ (ImageFormat::Jpeg | ImageFormat::Png)) => format,
+            _ => return Ok(None),
+        };
+
+        let (width, height, orientation) =
+            read_header(ImageReader::with_format(Cursor::new(&bytes), format))?;
+
+        let hash = format!("{:x}", Md5::digest(&bytes));
+        let stem = source
+            .file_stem()
+            .map(|x| slugify!(&x.to_string_lossy()))
+            .unwrap_or_default();
+
+        Ok(Some(SourceImage {
+            bytes,
+            format,
+            hash,
+            stem,
+            width,
+            height,
+            orientation,
+            decoded: None,
+        }))
+    }
+
+    fn get_variant(
+        &self,
+        image: &mut SourceImage,
+        width: u32,
+        format: ImageFormat,
+    ) -> Result<String> {
+        let extension = format.extensions_str()[0];
+        let file_name = format!(
+            "{}-{}-{}.{}",
+            image.stem,
+            &image.hash[..8],
+            width,
+            extension
+        );
+        let output_file = Path::new(&self.output_dir)
+            .join(IMAGES_DIR)
+            .join(&file_name);
+
+        if !output_file.exists() {
+            // Variants are upright, entries cached before the orientation was applied don't match
+            let cache_key = format!(
+                "image:{}:{}:{}:{}:{:?}",
+                image.hash,
+                width,
+                extension,
+                self.settings.get_quality(),
+                image.orientation
+            );
+
+            let data = match self.cache.as_ref().and_then(|c| c.get_bytes(&cache_key)) {
+                Some(data) => data,
+                None => {
+                    if image.decoded.is_none() {
+                        let mut decoded =
+                            image::load_from_memory_with_format(&image.bytes, image.format)?;
+                        // Re-encoding drops the EXIF data, so the pixels have to be rotated
+                        decoded.apply_orientation(image.orientation);
+                        image.decoded = Some(decoded);
+                    }
+                    let decoded = image.decoded.as_ref().context("Failed to decode image")?;
+
+                    let data = self.encode(decoded, width, format)?;
+                    if let Some(cache) = &self.cache {
+                        cache.set_bytes(&cache_key, &data)?;
+                    }
+                    data
+                }
+            };
+
+            fs::create_dir_all(
+                output_file
+                    .parent()
+                    .context("Failed to get parent folder")?,
+            )?;
+            fs::write(&output_file, data)?;
+        }
+
+        Ok(format!(
+            "{}/{}/{}",
+            self.build_settings.get_base_path(),
+            IMAGES_DIR,
+            file_name
+        ))
+    }
+
+    pub fn process(&self, source: &Path) -> Result<Option<ProcessedImage>> {
+        let mut image = match self.load(source)? {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+
+        let mut widths = self
+            .settings
+            .get_widths()
+            .into_iter()
+            .filter(|x| *x > 0 && *x < image.width)
+            .collect::<Vec<u32>>();
+        widths.push(image.width);
+        widths.sort_unstable();
+        widths.dedup();
+
+        let configured_formats = self.settings.get_formats();
+        for format in &configured_formats {
+            if format != "avif" && format != "webp" {
+                Logger::new().error(&format!("Unsupported image format: {}", format));
+            }
+        }
+
+        // Browsers pick the first supported source, so list the smallest formats first.
+        // WebP is lossless, which only beats the original for PNG images
+        let mut formats = [("avif", ImageFormat::Avif), ("webp", ImageFormat::WebP)]
+            .into_iter()
+            .filter(|(name, _)| configured_formats.iter().any(|x| x == name))
+            .map(|(_, format)| format)
+            .filter(|x| *x != ImageFormat::WebP || image.format == ImageFormat::Png)
+            .collect::<Vec<ImageFormat>>();
+        formats.push(image.format);
+
+        let mut sources = vec![];
+
+        for format in formats {
+            let mut srcset = vec![];
+
+            for &width in &widths {
+                srcset.push((self.get_variant(&mut image, width, format)?, width));
+            }
+
+            sources.push(ImageSource {
+                mime_type: format.to_mime_type(),
+                srcset,
+            });
+        }
+
+        Ok(Some(ProcessedImage {
+            width: image.width,
+            height: image.height,
+            sources,
+        }))
+    }
+
+    pub fn thumbnail(&self, source: &Path) -> Result<Option<String>> {
+        let mut image = match self.load(source)? {
+            Some(image) => image,
+            None => return Ok(None),
+        };
+
+        let width = self.settings.get_thumbnail_width().min(image.width);
+        let format = image.format;
+
+        Ok(Some(self.get_variant(&mut image, width, format)?))
+    }
+
+    pub fn publish(&self, source: &Path) -> Result<String> {
+        let bytes = fs::read(source)?;
+
+        let hash = format!("{:x}", Md5::digest(&bytes));
+        let stem = source
+            .file_stem()
+            .map(|x| slugify!(&x.to_string_lossy()))
+            .unwrap_or_default();
+        let extension = source
+            .extension()
+            .map(|x| x.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let file_name = format!("{}-{}.{}", stem, &hash[..8], extension);
+        let output_file = Path::new(&self.output_dir)
+            .join(IMAGES_DIR)
+            .join(&file_name);
+
+        if !output_file.exists() {
+            self.copy_file(source, &output_file)?;
+        }
+
+        Ok(format!(
+            "{}/{}/{}",
+            self.build_settings.get_base_path(),
+            IMAGES_DIR,
+            file_name
+        ))
+    }
+
+    pub fn copy_file(&self, source: &Path, destination: &Path) -> Result<()> {
+        if let Some(folder) = destination.parent() {
+            fs::create_dir_all(folder)?;
+        }
+
+        let bytes = fs::read(source)?;
+
+        if self.settings.is_gps_stripped()
+            && matches!(image::guess_format(&bytes), Ok(ImageFormat::Jpeg))
+        {
+            fs::write(destination, strip_gps_metadata(&bytes)?)?;
+        } else {
+            fs::write(destination, bytes)?;
+        }
+
+        Ok(())
+    }
+
+    fn encode(&self, image: &DynamicImage, width: u32, format: ImageFormat) -> Result<Vec<u8>> {
+        let image = if width < image.width() {
+            image.resize(width, image.height(), FilterType::Lanczos3)
+        } else {
+            image.clone()
+        };
+
+        let quality = self.settings.get_quality();
+        let mut data = vec![];
+
+        match format {
+            ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+                .write_with_encoder(JpegEncoder::new_with_quality(&mut data, quality))?,
+            ImageFormat::WebP => DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_with_encoder(WebPEncoder::new_lossless(&mut data))?,
+            ImageFormat::Avif => DynamicImage::ImageRgba8(image.to_rgba8())
+                .write_with_encoder(AvifEncoder::new_with_speed_quality(&mut data, 8, quality))?,
+            _ => image.write_to(&mut Cursor::new(&mut data), format)?,
+        }
+
+        Ok(data)
+    }
+}
+
+// Size an image is displayed at, whatever its format
+pub fn get_dimensions(file: &Path) -> Result<(u32, u32)> {
+    let (width, height, _) = read_header(ImageReader::open(file)?.with_guessed_format()?)?;
+
+    Ok((width, height))
+}
+
+// Size and EXIF orientation of an image, the size is the one it is displayed at
+fn read_header<R: BufRead + Seek>(reader: ImageReader<R>) -> Result<(u32, u32, Orientation)> {
+    let mut decoder = reader.into_decoder().context("Failed to read image")?;
+    // Broken EXIF data leaves the image as it is
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let (width, height) = decoder.dimensions();
+
+    Ok(match orientation {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => (height, width, orientation),
+        _ => (width, height, orientation),
+    })
+}
+
+// Rewrites the EXIF segment of a JPEG without the GPS fields, all other
+// segments are kept byte for byte
+fn strip_gps_metadata(bytes: &[u8]) -> Result<Vec<u8>> {
+    const EXIF_ID: &[u8] = b"Exif\0\0";
+
+    if bytes.len() < 4 || bytes[0..2] != [0xff, 0xd8] {
+        return Err(anyhow::anyhow!("Not a JPEG file"));
+    }
+
+    let mut output = bytes[0..2].to_vec();
+    let mut position = 2;
+
+    while position + 4 <= bytes.len() && bytes[position] == 0xff {
+        let marker = bytes[position + 1];
+
+        // Start of scan, the rest of the file is image data
+        if marker == 0xda {
+            break;
+        }
+
+        let length = u16::from_be_bytes([bytes[position + 2], bytes[position + 3]]) as usize;
+        if length < 2 {
+            return Err(anyhow::anyhow!("Broken JPEG file"));
+        }
+        let end = position + 2 + length;
+        let segment = bytes.get(position..end).context("Broken JPEG file")?;
+        let data = &segment[4..];
+
+        if marker == 0xe1 && data.starts_with(EXIF_ID) {
+            let exif = exif::Reader::new().read_raw(data[EXIF_ID.len()..].to_vec())?;
+
+            if exif.fields().any(|x| x.tag.context() == exif::Context::Gps) {
+                let mut writer = exif::experimental::Writer::new();
+                for field in exif.fields() {
+                    // The thumbnail is dropped as its image data isn't carried over
+                    if field.tag.context() != exif::Context::Gps
+                        && field.ifd_num == exif::In::PRIMARY
+                    {
+                        writer.push_field(field);
+                    }
+                }
+
+                let mut tiff = Cursor::new(vec![]);
+                writer.write(&mut tiff, exif.little_endian())?;
+                let tiff = tiff.into_inner();
+
+                let length = u16::try_from(tiff.len() + EXIF_ID.len() + 2)
+                    .context("EXIF data is too large")?;
+                output.extend_from_slice(&[0xff, 0xe1]);
+                output.extend_from_slice(&length.to_be_bytes());
+                output.extend_from_slice(EXIF_ID);
+                output.extend_from_slice(&tiff);
+                position = end;
+                continue;
+            }
+        }
+
+        output.extend_from_slice(segment);
+        position = end;
+    }
+
+    output.extend_from_slice(&bytes[position..]);
+
+    Ok(output)
+}
+
+pub fn render_plain_image(url: &str, alt: &str, title: &str) -> String {
+    let mut html = String::from("<img src=\"");
+    let _ = escape_href(&mut html, url);
+    html.push_str("\" alt=\"");
+    let _ = escape_html(&mut html, alt);
+    if !title.is_empty() {
+        html.push_str("\" title=\"");
+        let _ = escape_html(&mut html, title);
+    }
+    html.push_str("\" />");
+
+    html
+}
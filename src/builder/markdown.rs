@@ -0,0 +1,294 @@
+use pulldown_cmark::{CodeBlockKind, Event, Tag};
+
+pub const HOOKS: [&str; 5] = ["link", "image", "heading", "codeblock", "callout"];
+
+pub const CALLOUT_KINDS: [&str; 5] = ["note", "tip", "important", "warning", "caution"];
+
+// Markdown elements whose rendering can be overridden by a theme hook
+pub enum Element {
+    Link {
+        destination: String,
+        title: String,
+    },
+    Image {
+        destination: String,
+        title: String,
+    },
+    Heading {
+        level: usize,
+        id: Option<String>,
+        classes: Vec<String>,
+    },
+    CodeBlock {
+        language: String,
+    },
+    // Rendered as a callout when it starts with a `[!NOTE]` style marker
+    BlockQuote,
+}
+
+pub struct Callout {
+    pub kind: String,
+    pub title: String,
+}
+
+impl Callout {
+    fn new(kind: &str, title: &str) -> Option<Self> {
+        let kind = kind.to_lowercase();
+        if !CALLOUT_KINDS.contains(&kind.as_str()) {
+            return None;
+        }
+
+        let title = if title.trim().is_empty() {
+            let mut chars = kind.chars();
+            chars
+                .next()
+                .map(|x| x.to_uppercase().chain(chars).collect())
+                .unwrap_or_default()
+        } else {
+            title.trim().to_string()
+        };
+
+        Some(Self { kind, title })
+    }
+}
+
+pub enum Block {
+    Text(String, usize),
+    // Callout, its markdown content and the line the content starts on
+    Callout(Callout, String, usize),
+}
+
+impl Element {
+    pub fn from_tag(tag: &Tag) -> Option<Self> {
+        match tag {
+            Tag::Link(_, destination, title) => Some(Element::Link {
+                destination: destination.to_string(),
+                title: title.to_string(),
+            }),
+            Tag::Image(_, destination, title) => Some(Element::Image {
+                destination: destination.to_string(),
+                title: title.to_string(),
+            }),
+            Tag::Heading(level, id, classes) => Some(Element::Heading {
+                level: *level as usize,
+                id: id.map(|x| x.to_string()),
+                classes: classes.iter().map(|x| x.to_string()).collect(),
+            }),
+            Tag::CodeBlock(kind) => Some(Element::CodeBlock {
+                language: match kind {
+                    CodeBlockKind::Fenced(info) => info
+                        .split_whitespace()
+                        .next()
+                        .unwrap_or_default()
+                        .to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                },
+            }),
+            Tag::BlockQuote => Some(Element::BlockQuote),
+            _ => None,
+        }
+    }
+
+    pub fn hook_name(&self) -> &'static str {
+        match self {
+            Element::Link { .. } => "link",
+            Element::Image { .. } => "image",
+            Element::Heading { .. } => "heading",
+            Element::CodeBlock { .. } => "codeblock",
+            Element::BlockQuote => "callout",
+        }
+    }
+
+    pub fn is_end(&self, tag: &Tag) -> bool {
+        matches!(
+            (self, tag),
+            (Element::Link { .. }, Tag::Link(..))
+                | (Element::Image { .. }, Tag::Image(..))
+                | (Element::Heading { .. }, Tag::Heading(..))
+                | (Element::CodeBlock { .. }, Tag::CodeBlock(..))
+                | (Element::BlockQuote, Tag::BlockQuote)
+        )
+    }
+}
+
+pub fn is_external_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+pub fn events_to_html(events: Vec<Event>) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.into_iter());
+    html
+}
+
+pub fn events_to_text(events: &[Event]) -> String {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+            Event::SoftBreak | Event::HardBreak => Some("\n"),
+            _ => None,
+        })
+        .collect()
+}
+
+// Drops tags and comments from rendered HTML
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let end = if rest[start..].starts_with("<!--") {
+            rest[start..].find("-->").map(|x| start + x + 3)
+        } else {
+            rest[start..].find('>').map(|x| start + x + 1)
+        };
+        rest = &rest[end.unwrap_or(rest.len())..];
+    }
+    text.push_str(rest);
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+// Chinese and Japanese aren't separated by spaces, so every character counts as a word
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'
+            | '\u{3400}'..='\u{4DBF}'
+            | '\u{4E00}'..='\u{9FFF}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{20000}'..='\u{2FA1F}')
+}
+
+pub fn count_words(text: &str) -> usize {
+    let mut count = 0;
+    let mut in_word = false;
+
+    for c in text.chars() {
+        if is_cjk(c) {
+            count += 1;
+            in_word = false;
+        } else if c.is_whitespace()
+            || matches!(c, '\u{3000}'..='\u{303F}' | '\u{FF00}'..='\u{FFEF}')
+        {
+            in_word = false;
+        } else if !in_word {
+            count += 1;
+            in_word = true;
+        }
+    }
+
+    count
+}
+
+// Removes a GitHub style `[!NOTE] Optional title` marker from the start of a
+// blockquote's content
+pub fn take_callout(events: &mut Vec<Event>) -> Option<Callout> {
+    if !matches!(events.first(), Some(Event::Start(Tag::Paragraph))) {
+        return None;
+    }
+
+    let end = events
+        .iter()
+        .position(|x| {
+            matches!(
+                x,
+                Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph)
+            )
+        })
+        .unwrap_or(events.len());
+
+    // Formatting in the title is dropped
+    let first_line = events_to_text(&events[1..end]);
+
+    let (kind, title) = first_line.trim().strip_prefix("[!")?.split_once(']')?;
+    let callout = Callout::new(kind, title)?;
+
+    match events.get(end) {
+        Some(Event::End(Tag::Paragraph)) => {
+            events.drain(0..=end);
+        }
+        _ => {
+            events.drain(1..(end + 1).min(events.len()));
+        }
+    }
+
+    Some(callout)
+}
+
+// Splits out `:::note Optional title` ... `:::` containers, ignoring fenced code
+pub fn parse_containers(source: &str, first_line: usize) -> Vec<Block> {
+    let mut blocks = vec![];
+    let mut text = String::new();
+    let mut text_line = first_line;
+    let mut fence: Option<&str> = None;
+    // Callout, its content, content line and nesting depth
+    let mut container: Option<(Callout, String, usize, usize)> = None;
+    let mut opening_line = "";
+
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let line_number = first_line + index;
+        let trimmed = line.trim();
+
+        let mut in_code = fence.is_some();
+        if let Some(marker) = ["```", "~~~"].into_iter().find(|x| trimmed.starts_with(x)) {
+            match fence {
+                Some(open) if open == marker => fence = None,
+                None => fence = Some(marker),
+                _ => {}
+            }
+            in_code = true;
+        }
+
+        let opening = (!in_code)
+            .then(|| trimmed.strip_prefix(":::"))
+            .flatten()
+            .filter(|x| !x.is_empty())
+            .map(|x| x.split_once(char::is_whitespace).unwrap_or((x, "")));
+        let is_closing = !in_code && trimmed == ":::";
+
+        match container.as_mut() {
+            None => match opening.and_then(|(kind, title)| Callout::new(kind, title)) {
+                Some(callout) => {
+                    blocks.push(Block::Text(std::mem::take(&mut text), text_line));
+                    container = Some((callout, String::new(), line_number + 1, 1));
+                    opening_line = line;
+                }
+                _ => text.push_str(line),
+            },
+            Some((_, content, _, depth)) => {
+                if opening.is_some() {
+                    *depth += 1;
+                } else if is_closing {
+                    *depth -= 1;
+                }
+
+                if *depth > 0 {
+                    content.push_str(line);
+                } else if let Some((callout, content, content_line, _)) = container.take() {
+                    // Keep the container apart from surrounding paragraphs
+                    blocks.push(Block::Callout(callout, content, content_line));
+                    text_line = line_number + 1;
+                }
+            }
+        }
+    }
+
+    // An unclosed container is left as regular text
+    if let Some((_, content, content_line, _)) = container {
+        blocks.push(Block::Text(
+            format!("{}{}", opening_line, content),
+            content_line - 1,
+        ));
+    } else {
+        blocks.push(Block::Text(text, text_line));
+    }
+
+    blocks
+}
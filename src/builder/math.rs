@@ -0,0 +1,163 @@
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+pub enum Node {
+    Text(String),
+    Math(Math),
+}
+
+pub struct Math {
+    pub latex: String,
+    pub display: bool,
+    pub line: usize,
+}
+
+impl Math {
+    pub fn to_mathml(&self, file: &str) -> Result<String> {
+        let style = if self.display {
+            DisplayStyle::Block
+        } else {
+            DisplayStyle::Inline
+        };
+
+        let error = |e: &dyn std::fmt::Display| {
+            anyhow!(
+                "Failed to render math `{}` at {}:{}, {}",
+                self.latex.trim(),
+                file,
+                self.line,
+                e
+            )
+        };
+
+        let mathml = latex_to_mathml(self.latex.trim(), style).map_err(|e| error(&e))?;
+
+        // Some errors are written into the output instead of being returned
+        match mathml.split_once("[PARSE ERROR: ") {
+            Some((_, message)) => Err(error(
+                &message.split_once(']').map(|x| x.0).unwrap_or(message),
+            )),
+            None => Ok(mathml),
+        }
+    }
+}
+
+// Byte ranges of code blocks, fenced or indented, and code spans
+pub fn get_code_ranges(source: &str) -> Vec<Range<usize>> {
+    Parser::new_ext(source, Options::all())
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+// Splits out `$inline$` and `$$display$$` math of `source[range]`, ignoring code and
+// escaped `\$`. Code ranges and lines are those of the whole source
+pub fn parse(
+    source: &str,
+    range: Range<usize>,
+    first_line: usize,
+    code: &[Range<usize>],
+) -> Vec<Node> {
+    let line_at = |offset: usize| first_line + source[..offset].matches('\n').count();
+    let in_code = |start: usize, end: usize| code.iter().find(|x| x.start < end && start < x.end);
+
+    let mut nodes = vec![];
+    let mut text_start = range.start;
+    let mut position = range.start;
+
+    while position < range.end {
+        if let Some(code) = in_code(position, position + 1) {
+            position = code.end.min(range.end);
+            continue;
+        }
+
+        let rest = &source[position..range.end];
+        let mut chars = rest.chars();
+        let current = chars.next().unwrap_or_default();
+
+        match current {
+            '\\' => {
+                position += 1 + chars.next().map(|x| x.len_utf8()).unwrap_or_default();
+            }
+            '$' => match read_math(rest)
+                .filter(|(length, _, _)| in_code(position, position + length).is_none())
+            {
+                Some((length, latex, display)) => {
+                    nodes.push(Node::Text(source[text_start..position].to_string()));
+                    nodes.push(Node::Math(Math {
+                        latex: latex.to_string(),
+                        display,
+                        line: line_at(position),
+                    }));
+                    position += length;
+                    text_start = position;
+                }
+                None => position += 1,
+            },
+            _ => position += current.len_utf8(),
+        }
+    }
+
+    nodes.push(Node::Text(source[text_start..range.end].to_string()));
+
+    nodes
+}
+
+// Returns the length of the math expression starting at `source`, its content and
+// whether it is display math
+fn read_math(source: &str) -> Option<(usize, &str, bool)> {
+    if let Some(content) = source.strip_prefix("$$") {
+        let end = content.find("$$")?;
+        return (!content[..end].trim().is_empty()).then_some((end + 4, &content[..end], true));
+    }
+
+    let content = &source[1..];
+    // `$5 and $10` isn't math, the content can't start or end with whitespace
+    if content.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut previous = ' ';
+    let mut chars = content.char_indices().peekable();
+    while let Some((index, current)) = chars.next() {
+        match current {
+            '\\' => {
+                chars.next();
+            }
+            // Inline math doesn't span paragraphs or code spans
+            '`' => return None,
+            '\n' if ends_paragraph(&content[index..]) => return None,
+            '$' if !previous.is_whitespace()
+                && !chars.peek().is_some_and(|(_, x)| x.is_ascii_digit()) =>
+            {
+                return (index > 0).then_some((index + 2, &content[..index], false));
+            }
+            _ => {}
+        }
+        previous = current;
+    }
+
+    None
+}
+
+// Whether the text contains a blank line
+fn ends_paragraph(text: &str) -> bool {
+    let mut lines = text.split('\n').skip(1).peekable();
+    while let Some(line) = lines.next() {
+        // The last line is only partially covered
+        if lines.peek().is_none() {
+            return false;
+        }
+        if line.trim().is_empty() {
+            return true;
+        }
+    }
+
+    false
+}
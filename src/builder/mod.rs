@@ -9,8 +9,10 @@ use fs_extra::dir::CopyOptions;
 use html_minifier::HTMLMinifier;
 use owo_colors::OwoColorize;
 use rayon::prelude::*;
+use regex::Regex;
 use slugify::slugify;
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     fs,
     path::{Path, PathBuf},
 };
@@ -18,22 +20,51 @@ use tokio::time::Instant;
 use walkdir::WalkDir;
 
 pub mod cache;
+mod defaults;
+mod front_matter;
+mod gallery;
 mod handlebar_helpers;
+mod images;
+mod markdown;
+mod math;
+mod redirects;
 mod render;
+mod schema;
 mod seo;
+mod shortcodes;
+mod theme;
 
 pub const PAGES_DIR: &str = "pages";
 pub const PUBLIC_DIR: &str = "public";
 pub const THEME_DIR: &str = "theme";
 pub const OUTPUT_DIR: &str = "_site";
 
+pub struct SiteDirectory {
+    pub root: serde_yaml::Value,
+    pub pages: serde_yaml::Value,
+}
+
+// Settings and theme chain, resolved once for a whole build or check
+struct BuildContext {
+    // Files are named relative to it in messages
+    input_dir: String,
+    settings: settings::Settings,
+    theme_dirs: Vec<String>,
+    // `theme` template variable
+    theme_params: serde_yaml::Value,
+    // Theme templates, compiled once and shared by every page
+    handlebars: handlebars::Handlebars<'static>,
+}
+
 pub struct Worker {
+    input_dir: String,
     pages_dir: String,
     public_dir: String,
     theme_dir: String,
     output_dir: String,
     config_file: String,
     cache: Option<cache::Cache>,
+    image_cache: Option<cache::Cache>,
     is_dev: bool,
 }
 
@@ -44,36 +75,42 @@ impl Worker {
         let public_dir = utils::path_to_string(&input_dir.join(PUBLIC_DIR))?;
         let theme_dir = utils::path_to_string(&input_dir.join(THEME_DIR))?;
         let config_file = utils::path_to_string(&input_dir.join("Settings.toml"))?;
+        let input_dir = utils::path_to_string(&input_dir.to_path_buf())?;
 
         utils::create_dir_in_path(&PathBuf::from(output_dir))?;
 
         Ok(Self {
             output_dir: output_dir.to_string(),
+            input_dir,
             pages_dir,
             public_dir,
             theme_dir,
             config_file,
+            image_cache: cache.clone(),
             cache,
             is_dev,
         })
     }
 
-    pub fn prod(input_dir: &Path) -> Result<Self> {
+    pub fn prod(input_dir: &Path, image_cache: Option<cache::Cache>) -> Result<Self> {
         let output_dir = OUTPUT_DIR;
         let pages_dir = utils::path_to_string(&input_dir.join(PAGES_DIR))?;
         let public_dir = utils::path_to_string(&input_dir.join(PUBLIC_DIR))?;
         let theme_dir = utils::path_to_string(&input_dir.join(THEME_DIR))?;
         let config_file = utils::path_to_string(&input_dir.join("Settings.toml"))?;
+        let input_dir = utils::path_to_string(&input_dir.to_path_buf())?;
 
         utils::create_dir_in_path(&PathBuf::from(output_dir))?;
 
         Ok(Self {
             output_dir: output_dir.to_string(),
+            input_dir,
             pages_dir,
             public_dir,
             theme_dir,
             config_file,
             cache: None,
+            image_cache,
             is_dev: false,
         })
     }
@@ -103,8 +140,93 @@ impl Worker {
         &self.output_dir
     }
 
+    fn get_image_processor(&self, settings: &settings::Settings) -> images::ImageProcessor {
+        images::ImageProcessor::new(
+            settings.get_image_settings(),
+            settings.get_build_settings(),
+            &self.public_dir,
+            &self.output_dir,
+            self.image_cache.clone(),
+        )
+    }
+
+    fn get_render<'a>(
+        &self,
+        context: &'a BuildContext,
+        file: &str,
+        defaults: serde_yaml::Value,
+    ) -> render::Render<'a> {
+        render::Render::new(
+            file,
+            context,
+            self.cache.clone(),
+            self.get_image_processor(&context.settings),
+            defaults,
+        )
+    }
+
+    // Reads the theme manifests and compiles the templates once, reporting any
+    // problem with the theme
+    fn get_context(&self, process_images: bool) -> Result<BuildContext> {
+        let theme_dirs = self.read_theme_dirs()?;
+        let manifests = theme_dirs
+            .iter()
+            .map(|x| theme::read_manifest(x))
+            .collect::<Result<Vec<theme::Manifest>>>()?;
+
+        for manifest in &manifests {
+            manifest.check(&theme_dirs)?;
+        }
+
+        let mut settings = self.read_settings(&manifests);
+        if !process_images {
+            settings.images = None;
+        }
+        let theme_params = theme::get_params(&manifests, &settings);
+
+        let mut handlebars = render::get_handlebars(&settings, self.get_image_processor(&settings));
+        check_templates(
+            render::register_templates(&mut handlebars, &theme_dirs),
+            &self.input_dir,
+        )?;
+
+        Ok(BuildContext {
+            input_dir: self.input_dir.clone(),
+            settings,
+            theme_dirs,
+            theme_params,
+            handlebars,
+        })
+    }
+
     pub fn get_settings(&self) -> settings::Settings {
-        match Config::builder()
+        // An invalid manifest is reported by the build
+        let manifests = self
+            .get_theme_dirs()
+            .iter()
+            .filter_map(|x| theme::read_manifest(x).ok())
+            .collect::<Vec<theme::Manifest>>();
+
+        self.read_settings(&manifests)
+    }
+
+    fn read_settings(&self, manifests: &[theme::Manifest]) -> settings::Settings {
+        let mut config = Config::builder();
+
+        // Theme defaults come first, parents before children, so that `Settings.toml`
+        // overrides them
+        for manifest in manifests.iter().rev() {
+            if let Some(defaults) = manifest
+                .settings
+                .as_ref()
+                .and_then(|x| toml::to_string(x).ok())
+            {
+                config =
+                    config.add_source(config::File::from_str(&defaults, config::FileFormat::Toml));
+            }
+        }
+
+        match config
             .add_source(config::File::with_name(&self.config_file))
             .build()
         {
@@ -122,6 +244,42 @@ impl Worker {
         }
     }
 
+    // Reports template, front matter, layout and schema errors without building the site
+    pub fn check(&self) -> Result<()> {
+        let (markdown_files, defaults_files, _) = self.get_page_files();
+
+        // Nothing is written, images included
+        let context = self.get_context(false)?;
+        self.check_front_matter(&context, &markdown_files, &defaults_files)?;
+        let page_defaults = self.get_page_defaults(&context, &markdown_files, &defaults_files)?;
+
+        let pages_with_metadata = markdown_files
+            .par_iter()
+            .map(|file| {
+                let metadata = self
+                    .get_render(&context, file, page_defaults[file].clone())
+                    .get_metadata()?
+                    .unwrap_or(serde_yaml::Value::Null);
+
+                Ok((file.to_string(), metadata))
+            })
+            .collect::<Result<Vec<(String, serde_yaml::Value)>>>()?;
+
+        self.check_layouts(&context.theme_dirs, &pages_with_metadata)?;
+        self.check_schemas(&context.theme_dirs, &pages_with_metadata)?;
+
+        let errors = markdown_files
+            .par_iter()
+            .filter_map(|file| {
+                self.get_render(&context, file, page_defaults[file].clone())
+                    .check_body()
+                    .err()
+            })
+            .collect::<Vec<anyhow::Error>>();
+
+        join_errors(errors)
+    }
+
     pub fn build(&self) -> Result<()> {
         let log = Logger::new();
         log.activity("Building site");
@@ -131,56 +289,123 @@ impl Worker {
         self.setup_output()?;
         self.copy_public_files()?;
 
-        let markdown_files: Vec<String> = WalkDir::new(&self.pages_dir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.path().display().to_string().ends_with(".md"))
-            .map(|e| e.path().display().to_string())
-            .collect();
+        let (markdown_files, defaults_files, page_files) = self.get_page_files();
 
-        // Used for generating site directory
-        let all_pages_with_metadata: Vec<(String, String)> = markdown_files
+        let context = self.get_context(true)?;
+        let settings = &context.settings;
+        self.check_front_matter(&context, &markdown_files, &defaults_files)?;
+        let page_defaults = self.get_page_defaults(&context, &markdown_files, &defaults_files)?;
+
+        // Front matter can change where a page ends up, so it has to be read
+        // before any output path is known
+        let entries = markdown_files
             .par_iter()
-            .map(|x| {
-                let metadata = render::Render::new(
-                    x,
-                    &self.theme_dir,
-                    self.get_settings(),
-                    self.cache.clone(),
+            .map(|file| {
+                let metadata = self
+                    .get_render(&context, file, page_defaults[file].clone())
+                    .get_directory_entry()?;
+
+                Ok((file.to_string(), metadata))
+            })
+            .collect::<Vec<Result<(String, serde_yaml::Value)>>>();
+        let mut errors = vec![];
+        let pages_with_metadata = entries
+            .into_iter()
+            .filter_map(|x| x.map_err(|e| errors.push(e)).ok())
+            .collect::<Vec<(String, serde_yaml::Value)>>();
+        join_errors(errors)?;
+
+        // Gallery images are published under `_images`, not copied next to the page
+        let gallery_files = self.get_gallery_files(&pages_with_metadata);
+        let asset_files = page_files
+            .into_iter()
+            .filter(|x| !gallery_files.contains(Path::new(x)))
+            .collect::<Vec<String>>();
+
+        let routes = self.get_routes(settings, &pages_with_metadata)?;
+        let links = self.get_links(settings, &pages_with_metadata, &routes)?;
+
+        let aliases = pages_with_metadata
+            .iter()
+            .map(|(file, metadata)| {
+                redirects::get_aliases(
+                    file,
+                    &self.input_dir,
+                    metadata,
+                    &self.get_url_path(settings, &routes[file]),
                 )
-                .get_metadata()
-                .unwrap_or(None);
-
-                let x = x.replace(&self.pages_dir, "").replace("page.md", "");
-
-                let x = if x.contains(".md") {
-                    let x = x
-                        .replace(".md", "")
-                        .split('/')
-                        .map(|x| slugify!(x))
-                        .collect::<Vec<String>>()
-                        .join("/");
-                    x
-                } else {
-                    x
-                };
-
-                (x, metadata.unwrap_or("".to_string()))
             })
-            .filter(|x| x.0 != "/" || x.1.is_empty())
+            .collect::<Result<Vec<Vec<redirects::Alias>>>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<redirects::Alias>>();
+
+        self.check_output_paths(
+            settings,
+            &pages_with_metadata,
+            &routes,
+            &aliases,
+            &asset_files,
+        )?;
+        self.check_layouts(&context.theme_dirs, &pages_with_metadata)?;
+        self.check_schemas(&context.theme_dirs, &pages_with_metadata)?;
+
+        self.copy_page_assets(settings, &asset_files, &routes)?;
+
+        // Used for generating site directory
+        let all_pages_with_metadata: Vec<(String, serde_yaml::Value)> = pages_with_metadata
+            .into_iter()
+            .filter_map(|(file, metadata)| {
+                let html_file = &routes[&file];
+
+                // The directory follows the source tree with pretty urls whatever
+                // the url style, `slug` and `permalink` only change the url
+                let x = get_directory_key(
+                    &self.output_dir,
+                    &self.get_html_file_path(&file),
+                    file.ends_with("page.md"),
+                );
+
+                (x != "/" || metadata.is_null()).then(|| {
+                    let metadata = self.add_computed_fields(
+                        settings,
+                        &file,
+                        html_file,
+                        metadata,
+                        &markdown_files,
+                    );
+                    (x, metadata)
+                })
+            })
             .collect();
 
-        let site_directory = self.generate_site_directory(&all_pages_with_metadata)?;
+        let site_directory = SiteDirectory {
+            root: self.generate_site_directory(&all_pages_with_metadata)?,
+            pages: self.generate_pages_list(&all_pages_with_metadata),
+        };
 
-        markdown_files.par_iter().for_each(|file| {
-            if let Err(e) = self.process_file(file, &site_directory) {
-                log.error(&format!("{}: {}", "Failed to process file, ", e));
-            }
-        });
+        // Every page is written even when some fail, which are all reported at the end
+        let errors = markdown_files
+            .par_iter()
+            .filter_map(|file| {
+                self.process_file(
+                    &context,
+                    file,
+                    &routes[file],
+                    &page_defaults[file],
+                    &links,
+                    &site_directory,
+                )
+                .err()
+            })
+            .collect::<Vec<anyhow::Error>>();
+        join_errors(errors)?;
+
+        self.generate_redirects(settings, &aliases)?;
 
         // Handle robots.txt, ignore if there is a file already
         if !Path::new(&self.output_dir).join("robots.txt").exists() {
-            if let Ok(robots_txt) = seo::generate_robots_txt(&self.get_settings()) {
+            if let Ok(robots_txt) = seo::generate_robots_txt(settings) {
                 log.success(&format!(
                     "{} {} robots.txt",
                     "Generated",
@@ -192,9 +417,7 @@ impl Worker {
 
         // Handle sitemap.xml, ignore if there is a file already
         if !Path::new(&self.output_dir).join("sitemap.xml").exists() {
-            if let Ok(sitemap_xml) =
-                seo::generate_sitemap_xml(&self.get_settings(), &all_pages_with_metadata)
-            {
+            if let Ok(sitemap_xml) = seo::generate_sitemap_xml(settings, &all_pages_with_metadata) {
                 log.success(&format!(
                     "{} {} sitemap.xml",
                     "Generated",
@@ -210,15 +433,121 @@ impl Worker {
         Ok(())
     }
 
-    fn process_file(&self, file: &str, site_directory: &serde_yaml::Value) -> Result<()> {
-        let log = Logger::new();
+    // Markdown pages, `_defaults.yaml` files and the other files in `pages`
+    fn get_page_files(&self) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let (markdown_files, asset_files): (Vec<String>, Vec<String>) =
+            WalkDir::new(&self.pages_dir)
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.file_type().is_file())
+                .map(|e| e.path().display().to_string())
+                .partition(|x| x.ends_with(".md"));
+        let (defaults_files, asset_files): (Vec<String>, Vec<String>) = asset_files
+            .into_iter()
+            .partition(|x| defaults::is_defaults_file(x));
+
+        (markdown_files, defaults_files, asset_files)
+    }
 
+    // Images of every page `gallery`, a missing folder is reported when the page is rendered
+    fn get_gallery_files(
+        &self,
+        pages_with_metadata: &[(String, serde_yaml::Value)],
+    ) -> HashSet<PathBuf> {
+        pages_with_metadata
+            .iter()
+            .filter_map(|(file, metadata)| {
+                let gallery_dir = metadata.get("gallery")?.as_str()?;
+                gallery::get_gallery_files(file, gallery_dir).ok()
+            })
+            .flatten()
+            .collect()
+    }
+
+    // The site's theme and its parents, an invalid chain is reported by the build
+    pub fn get_theme_dirs(&self) -> Vec<String> {
+        self.read_theme_dirs()
+            .unwrap_or_else(|_| vec![self.theme_dir.clone()])
+    }
+
+    fn read_theme_dirs(&self) -> Result<Vec<String>> {
+        // Read directly, the settings themselves depend on the theme
+        let parent = Config::builder()
+            .add_source(config::File::with_name(&self.config_file))
+            .build()?
+            .get_string("theme.parent")
+            .ok();
+
+        theme::get_theme_dirs(&self.theme_dir, parent.as_deref())
+    }
+
+    // Parses all front matter up front so that every error is reported at once
+    fn check_front_matter(
+        &self,
+        context: &BuildContext,
+        markdown_files: &[String],
+        defaults_files: &[String],
+    ) -> Result<()> {
+        let mut errors = markdown_files
+            .par_iter()
+            .filter_map(|file| {
+                self.get_render(context, file, serde_yaml::Value::Null)
+                    .get_metadata()
+                    .err()
+            })
+            .collect::<Vec<anyhow::Error>>();
+
+        errors.extend(defaults_files.iter().filter_map(|file| {
+            defaults::read_defaults_files(std::slice::from_ref(file), &self.input_dir).err()
+        }));
+
+        join_errors(errors)
+    }
+
+    // Defaults of every page from `_defaults.yaml` files and folder page `cascade`
+    fn get_page_defaults(
+        &self,
+        context: &BuildContext,
+        markdown_files: &[String],
+        defaults_files: &[String],
+    ) -> Result<HashMap<String, serde_yaml::Value>> {
+        let defaults = defaults::read_defaults_files(defaults_files, &self.input_dir)?;
+
+        let cascades = markdown_files
+            .iter()
+            .filter(|file| file.ends_with("page.md"))
+            .map(|file| {
+                match self
+                    .get_render(context, file, serde_yaml::Value::Null)
+                    .get_metadata()?
+                {
+                    Some(metadata) => defaults::get_cascade(file, &self.input_dir, &metadata),
+                    None => Ok(None),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        Ok(markdown_files
+            .iter()
+            .map(|file| {
+                (
+                    file.to_string(),
+                    defaults::get_page_defaults(&self.pages_dir, file, &defaults, &cascades),
+                )
+            })
+            .collect())
+    }
+
+    fn get_html_file_path(&self, file: &str) -> String {
         let html_file = file
             .replace(&self.pages_dir, &self.output_dir)
             .replace("page.md", "index.html");
 
-        let html_file = if html_file.contains(".md") {
-            let html_file = html_file
+        if html_file.contains(".md") {
+            html_file
                 .replace(".md", "/index.html")
                 .split('/')
                 .map(|x| {
@@ -229,23 +558,464 @@ impl Worker {
                     }
                 })
                 .collect::<Vec<String>>()
-                .join("/");
-            html_file
+                .join("/")
         } else {
             html_file
+        }
+    }
+
+    // Path of a file relative to the site, like `pages/blog/launch.md`
+    fn get_source_name(&self, file: &str) -> String {
+        utils::get_source_name(file, &self.input_dir)
+    }
+
+    // Output path of a page, taking `permalink` and `slug` front matter into account
+    fn resolve_html_file_path(&self, file: &str, metadata: &serde_yaml::Value) -> Result<String> {
+        let html_file = self.get_html_file_path(file);
+
+        if let Some(permalink) = metadata.get("permalink") {
+            let permalink = permalink.as_str().with_context(|| {
+                format!(
+                    "Invalid permalink in {}, expected a string",
+                    self.get_source_name(file)
+                )
+            })?;
+
+            let segments = permalink
+                .split('/')
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>();
+            if segments.iter().any(|x| *x == "." || *x == "..") {
+                return Err(anyhow::anyhow!(
+                    "Invalid permalink `{}` in {}, it can't contain `.` or `..`",
+                    permalink,
+                    self.get_source_name(file)
+                ));
+            }
+
+            let mut path = vec![self.output_dir.as_str()];
+            path.extend(segments);
+            path.push("index.html");
+
+            return Ok(path.join("/"));
+        }
+
+        if let Some(slug) = metadata.get("slug") {
+            let slug = slug
+                .as_str()
+                .map(|x| slugify!(x))
+                .filter(|x| !x.is_empty())
+                .with_context(|| {
+                    format!(
+                        "Invalid slug in {}, expected a string",
+                        self.get_source_name(file)
+                    )
+                })?;
+
+            let folder = Path::new(&html_file)
+                .parent()
+                .context("Failed to get parent folder")?;
+
+            // The site root has no segment to replace
+            if folder != Path::new(&self.output_dir) {
+                return Ok(folder
+                    .with_file_name(slug)
+                    .join("index.html")
+                    .display()
+                    .to_string());
+            }
+        }
+
+        Ok(html_file)
+    }
+
+    // Output path of every page
+    fn get_routes(
+        &self,
+        settings: &settings::Settings,
+        pages_with_metadata: &[(String, serde_yaml::Value)],
+    ) -> Result<HashMap<String, String>> {
+        pages_with_metadata
+            .iter()
+            .map(|(file, metadata)| {
+                let html_file = self.resolve_html_file_path(file, metadata)?;
+                Ok((
+                    file.to_string(),
+                    self.apply_url_style(settings, file, html_file),
+                ))
+            })
+            .collect()
+    }
+
+    // Folder pages keep their index file so that relative links to their assets
+    // keep working, other pages become `name.html` with ugly urls
+    fn apply_url_style(
+        &self,
+        settings: &settings::Settings,
+        file: &str,
+        html_file: String,
+    ) -> String {
+        if !settings.get_build_settings().has_ugly_urls() || file.ends_with("page.md") {
+            return html_file;
+        }
+
+        match html_file.strip_suffix("/index.html") {
+            Some(path) if path != self.output_dir => format!("{}.html", path),
+            _ => html_file,
+        }
+    }
+
+    // Maps the pretty url of every page, without a trailing slash, to its actual url
+    fn get_links(
+        &self,
+        settings: &settings::Settings,
+        pages_with_metadata: &[(String, serde_yaml::Value)],
+        routes: &HashMap<String, String>,
+    ) -> Result<HashMap<String, String>> {
+        pages_with_metadata
+            .iter()
+            .map(|(file, metadata)| {
+                let html_file = self.resolve_html_file_path(file, metadata)?;
+                Ok((
+                    get_directory_key(&self.output_dir, &html_file, false),
+                    self.get_url_path(settings, &routes[file]),
+                ))
+            })
+            .collect()
+    }
+
+    // Points root relative links to pages at their url in the configured style
+    fn resolve_links(
+        &self,
+        settings: &settings::Settings,
+        html: &str,
+        links: &HashMap<String, String>,
+    ) -> Result<String> {
+        let href = Regex::new(r#"href="(/[^"]*)""#).context("Failed to parse link pattern")?;
+        let base_path = settings.get_build_settings().get_base_path();
+
+        Ok(href
+            .replace_all(html, |captures: &regex::Captures| {
+                let link = &captures[1];
+                let (path, suffix) = link
+                    .find(['?', '#'])
+                    .map(|x| link.split_at(x))
+                    .unwrap_or((link, ""));
+
+                let page = path.strip_prefix(&base_path).unwrap_or(path);
+                match links.get(page.trim_end_matches('/')) {
+                    Some(url) if !path.starts_with("//") && !page.is_empty() && page != "/" => {
+                        format!("href=\"{}{}\"", url, suffix)
+                    }
+                    _ => captures[0].to_string(),
+                }
+            })
+            .to_string())
+    }
+
+    // Fails with every source of a file that more than one would be written to,
+    // as the last one to be written would otherwise win at random
+    fn check_output_paths(
+        &self,
+        settings: &settings::Settings,
+        pages_with_metadata: &[(String, serde_yaml::Value)],
+        routes: &HashMap<String, String>,
+        aliases: &[redirects::Alias],
+        asset_files: &[String],
+    ) -> Result<()> {
+        let mut outputs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let source_name = |file: &str| file.replace(&self.pages_dir, PAGES_DIR);
+
+        for file in WalkDir::new(&self.public_dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let file = file.path().display().to_string();
+            outputs
+                .entry(file.replace(&self.public_dir, &self.output_dir))
+                .or_default()
+                .push(file.replace(&self.public_dir, PUBLIC_DIR));
+        }
+
+        for (file, metadata) in pages_with_metadata {
+            let html_file = &routes[file];
+            outputs
+                .entry(html_file.to_string())
+                .or_default()
+                .push(source_name(file));
+
+            let is_amp = metadata
+                .get("amp")
+                .and_then(|amp| amp.as_bool())
+                .unwrap_or(false);
+            if is_amp && !self.is_dev {
+                outputs
+                    .entry(self.get_amp_file_path(settings, html_file))
+                    .or_default()
+                    .push(format!("{} (amp)", source_name(file)));
+            }
+        }
+
+        for alias in aliases {
+            outputs
+                .entry(redirects::get_alias_file_path(&self.output_dir, alias))
+                .or_default()
+                .push(format!(
+                    "{} (alias {})",
+                    source_name(&alias.file),
+                    alias.from
+                ));
+        }
+
+        for file in asset_files {
+            outputs
+                .entry(
+                    self.get_asset_file_path(file, routes)?
+                        .display()
+                        .to_string(),
+                )
+                .or_default()
+                .push(source_name(file));
+        }
+
+        let collisions = outputs
+            .iter()
+            .filter(|(_, sources)| sources.len() > 1)
+            .map(|(output, sources)| {
+                let mut sources = sources.clone();
+                sources.sort();
+                format!("  {}\n    - {}", output, sources.join("\n    - "))
+            })
+            .collect::<Vec<String>>();
+
+        if !collisions.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Multiple files have the same output path:\n{}",
+                collisions.join("\n")
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Every layout used by a page has to exist in the theme
+    fn check_layouts(
+        &self,
+        theme_dirs: &[String],
+        pages_with_metadata: &[(String, serde_yaml::Value)],
+    ) -> Result<()> {
+        let mut missing: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+        for (file, metadata) in pages_with_metadata {
+            let layout = render::get_layout(&self.get_source_name(file), metadata)?;
+            if theme::find_file(theme_dirs, &format!("{}.hbs", layout)).is_none() {
+                missing
+                    .entry(format!("{}/{}.hbs", THEME_DIR, layout))
+                    .or_default()
+                    .push(file.replace(&self.pages_dir, PAGES_DIR));
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Layout templates are missing from the theme:\n{}",
+                missing
+                    .iter()
+                    .map(|(template, files)| format!(
+                        "  {}\n    - {}",
+                        template,
+                        files.join("\n    - ")
+                    ))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Front matter has to match the schema the theme declares for the page's template
+    fn check_schemas(
+        &self,
+        theme_dirs: &[String],
+        pages_with_metadata: &[(String, serde_yaml::Value)],
+    ) -> Result<()> {
+        let schemas = schema::read_schemas(theme_dirs)?;
+
+        let reports = pages_with_metadata
+            .iter()
+            .filter_map(|(file, metadata)| {
+                let template = metadata.get("template")?.as_str()?;
+                let (schema_file, schema) = schemas.get(template)?;
+                let violations = schema.validate(metadata);
+
+                (!violations.is_empty()).then(|| {
+                    format!(
+                        "  {} ({})\n    - {}",
+                        file.replace(&self.pages_dir, PAGES_DIR),
+                        schema_file.replace(&self.theme_dir, THEME_DIR),
+                        violations.join("\n    - ")
+                    )
+                })
+            })
+            .collect::<Vec<String>>();
+
+        if !reports.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Front matter doesn't match the theme schemas:\n{}",
+                reports.join("\n")
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn generate_redirects(
+        &self,
+        settings: &settings::Settings,
+        aliases: &[redirects::Alias],
+    ) -> Result<()> {
+        let log = Logger::new();
+
+        for alias in aliases {
+            let alias_file = redirects::get_alias_file_path(&self.output_dir, alias);
+            let folder = Path::new(&alias_file)
+                .parent()
+                .context("Failed to get parent folder")?;
+            fs::create_dir_all(folder)?;
+            fs::write(
+                &alias_file,
+                redirects::generate_redirect_page(settings, &alias.to),
+            )?;
+
+            log.success(&format!(
+                "{} {} {}",
+                "Generated",
+                "Redirect   ".blue(),
+                alias_file
+            ));
+        }
+
+        // Handle _redirects, ignore if there is a file already
+        let redirects_file = Path::new(&self.output_dir).join("_redirects");
+        if settings.get_build_settings().has_redirects_file()
+            && !aliases.is_empty()
+            && !redirects_file.exists()
+        {
+            fs::write(
+                redirects_file,
+                redirects::generate_redirects_file(
+                    aliases,
+                    &settings.get_build_settings().get_base_path(),
+                ),
+            )?;
+            log.success(&format!(
+                "{} {} _redirects",
+                "Generated",
+                "File       ".blue()
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn get_url_path(&self, settings: &settings::Settings, html_file: &str) -> String {
+        let build_settings = settings.get_build_settings();
+        let path = html_file.replacen(&self.output_dir, "", 1);
+
+        let url = if path == "/index.html" {
+            String::from("/")
+        } else if build_settings.has_ugly_urls() {
+            path
+        } else {
+            let url = path.strip_suffix("index.html").unwrap_or(&path);
+            if build_settings.has_trailing_slash() {
+                url.to_string()
+            } else {
+                url.trim_end_matches('/').to_string()
+            }
         };
 
-        let actual_url_path = html_file
-            .replace(&self.output_dir, "")
-            .replace("index.html", "");
+        format!("{}{}", build_settings.get_base_path(), url)
+    }
 
-        let html = render::Render::new(
-            file,
-            &self.theme_dir,
-            self.get_settings(),
-            self.cache.clone(),
-        )
-        .render_page("app", &actual_url_path, site_directory)?;
+    fn get_amp_file_path(&self, settings: &settings::Settings, html_file: &str) -> String {
+        if settings.get_build_settings().has_ugly_urls() {
+            let path = html_file.strip_suffix(".html").unwrap_or(html_file);
+            format!("{}.amp.html", path)
+        } else {
+            html_file.replace("index.html", "amp/index.html")
+        }
+    }
+
+    fn get_asset_file_path(&self, file: &str, routes: &HashMap<String, String>) -> Result<PathBuf> {
+        let asset = Path::new(file);
+        let file_name = asset.file_name().context("Failed to get asset file name")?;
+
+        // Assets live next to the page of their folder, so resolve the
+        // output folder the same way a `page.md` in there would be
+        let page_file = asset
+            .parent()
+            .context("Failed to get parent folder")?
+            .join("page.md");
+        let page_file = page_file.display().to_string();
+        let html_file = routes
+            .get(&page_file)
+            .cloned()
+            .unwrap_or_else(|| self.get_html_file_path(&page_file));
+        let folder = Path::new(&html_file)
+            .parent()
+            .context("Failed to get parent folder")?;
+
+        Ok(folder.join(file_name))
+    }
+
+    fn copy_page_assets(
+        &self,
+        settings: &settings::Settings,
+        asset_files: &[String],
+        routes: &HashMap<String, String>,
+    ) -> Result<()> {
+        let log = Logger::new();
+        let images = self.get_image_processor(settings);
+
+        for file in asset_files {
+            let asset = Path::new(file);
+            let asset_file = self.get_asset_file_path(file, routes)?;
+            images.copy_file(asset, &asset_file)?;
+
+            log.success(&format!(
+                "{} {} {}",
+                "Generated",
+                "Asset      ".blue(),
+                asset_file.display()
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn process_file(
+        &self,
+        context: &BuildContext,
+        file: &str,
+        html_file: &str,
+        defaults: &serde_yaml::Value,
+        links: &HashMap<String, String>,
+        site_directory: &SiteDirectory,
+    ) -> Result<()> {
+        let log = Logger::new();
+
+        let settings = &context.settings;
+        let actual_url_path = self.get_url_path(settings, html_file);
+        let amp_file = self.get_amp_file_path(settings, html_file);
+        let amp_url_path = self.get_url_path(settings, &amp_file);
+
+        let html = self
+            .get_render(context, file, defaults.clone())
+            .render_page("app", &actual_url_path, &amp_url_path, site_directory)?;
+        let html = self.resolve_links(settings, &html, links)?;
 
         let folder = Path::new(&html_file)
             .parent()
@@ -262,28 +1032,24 @@ impl Worker {
         if self.is_dev {
             // Add websocket client to html
             let html = format!("{}\n{}", html, WEBSOCKET_CLIENT_JS);
-            fs::write(&html_file, html)?;
+            fs::write(html_file, html)?;
             return Ok(());
         }
 
         let mut html_minifier = HTMLMinifier::new();
         html_minifier.digest(&html)?;
-        fs::write(&html_file, html_minifier.get_html())?;
+        fs::write(html_file, html_minifier.get_html())?;
 
         // Handle AMP
-        let amp = render::Render::new(
-            file,
-            &self.theme_dir,
-            self.get_settings(),
-            self.cache.clone(),
-        )
-        .render_page("amp", &actual_url_path, site_directory)?;
+        let amp = self
+            .get_render(context, file, defaults.clone())
+            .render_page("amp", &actual_url_path, &amp_url_path, site_directory)?;
+        let amp = self.resolve_links(settings, &amp, links)?;
 
         if amp.is_empty() {
             return Ok(());
         }
 
-        let amp_file = html_file.replace("index.html", "amp/index.html");
         log.success(&format!(
             "{} {} {}",
             "Generated".green(),
@@ -299,9 +1065,79 @@ impl Worker {
         Ok(())
     }
 
+    // Adds fields templates would otherwise have to work out from the file tree
+    fn add_computed_fields(
+        &self,
+        settings: &settings::Settings,
+        file: &str,
+        html_file: &str,
+        metadata: serde_yaml::Value,
+        markdown_files: &[String],
+    ) -> serde_yaml::Value {
+        let mut entry = match metadata {
+            serde_yaml::Value::Mapping(metadata) => metadata,
+            _ => serde_yaml::Mapping::new(),
+        };
+
+        let url = self.get_url_path(settings, html_file);
+        let source_path = file
+            .replace(&self.pages_dir, "")
+            .trim_start_matches('/')
+            .to_string();
+        let section = match source_path.split_once('/') {
+            Some((section, _)) => section.to_string(),
+            None => String::new(),
+        };
+
+        // A section is a folder page with other pages below it
+        let folder = file.trim_end_matches("page.md");
+        let is_section = file.ends_with("/page.md")
+            && markdown_files
+                .iter()
+                .any(|x| x != file && x.starts_with(folder));
+
+        let date_modified = fs::metadata(file)
+            .and_then(|x| x.modified())
+            .ok()
+            .map(|x| chrono::DateTime::<chrono::Utc>::from(x).timestamp_millis());
+
+        let has_amp = entry
+            .get("amp")
+            .and_then(|amp| amp.as_bool())
+            .unwrap_or(false);
+
+        // A front matter `permalink` is already part of `url`
+        entry.insert(
+            "permalink".into(),
+            settings.get_permalink(&url).unwrap_or_default().into(),
+        );
+        entry.insert("url".into(), url.into());
+        entry.insert("source_path".into(), source_path.into());
+        entry.insert("section".into(), section.into());
+        entry.insert("is_section".into(), is_section.into());
+        entry.insert(
+            "date_modified".into(),
+            date_modified.map_or(serde_yaml::Value::Null, |x| x.into()),
+        );
+        entry.insert("has_amp".into(), has_amp.into());
+
+        serde_yaml::Value::Mapping(entry)
+    }
+
+    // Every page in a flat list sorted by url, for templates that don't need the tree
+    fn generate_pages_list(&self, url_paths: &[(String, serde_yaml::Value)]) -> serde_yaml::Value {
+        let mut pages = url_paths
+            .iter()
+            .map(|(_, metadata)| metadata.clone())
+            .collect::<Vec<serde_yaml::Value>>();
+        pages.sort_by_key(|x| x.get("url").and_then(|x| x.as_str()).map(str::to_string));
+
+        serde_yaml::Value::Sequence(pages)
+    }
+
     pub fn generate_site_directory(
         &self,
-        url_paths: &Vec<(String, String)>,
+        url_paths: &Vec<(String, serde_yaml::Value)>,
     ) -> Result<serde_yaml::Value> {
         let mut yaml = serde_yaml::Mapping::new();
 
@@ -335,16 +1171,69 @@ impl Worker {
                     };
             }
 
-            if let Ok(metadata) = utils::parse_string_to_yaml(metadata) {
-                current_yaml.insert(serde_yaml::Value::String(last.to_string()), metadata);
-            } else {
-                current_yaml.insert(
-                    serde_yaml::Value::String(last.to_string()),
-                    serde_yaml::Value::Null,
-                );
-            }
+            current_yaml.insert(
+                serde_yaml::Value::String(last.to_string()),
+                metadata.clone(),
+            );
         }
 
         Ok(serde_yaml::Value::Mapping(yaml))
     }
 }
+
+// Site directory key of a page from its pretty output path, folder pages keep the
+// trailing slash so that they end up as `_self`
+fn get_directory_key(output_dir: &str, html_file: &str, is_folder_page: bool) -> String {
+    let path = html_file.replacen(output_dir, "", 1);
+    let url = path.strip_suffix("index.html").unwrap_or(&path);
+
+    if is_folder_page {
+        url.to_string()
+    } else {
+        url.trim_end_matches('/').to_string()
+    }
+}
+
+// Every template syntax error of the theme at once
+fn check_templates(
+    errors: Vec<(String, handlebars::TemplateError)>,
+    input_dir: &str,
+) -> Result<()> {
+    let errors = errors
+        .into_iter()
+        .map(|(file, e)| {
+            let position = match (e.line_no, e.column_no) {
+                (Some(line), Some(column)) => format!(":{}:{}", line, column),
+                _ => String::new(),
+            };
+            format!(
+                "Invalid template {}{}, {}",
+                utils::get_source_name(&file, input_dir),
+                position,
+                e.reason()
+            )
+        })
+        .collect::<Vec<String>>();
+
+    if !errors.is_empty() {
+        return Err(anyhow::anyhow!("{}", errors.join("\n")));
+    }
+
+    Ok(())
+}
+
+// Fails with every error, one after the other
+fn join_errors(errors: Vec<anyhow::Error>) -> Result<()> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow::anyhow!(
+        "{}",
+        errors
+            .iter()
+            .map(|e| e.to_string())
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    ))
+}
@@ -0,0 +1,106 @@
+use anyhow::{anyhow, Result};
+
+use crate::shared::{settings::Settings, utils};
+
+pub struct Alias {
+    // Page the alias is defined in
+    pub file: String,
+    pub from: String,
+    pub to: String,
+}
+
+// `aliases` front matter of a page, as absolute url paths
+pub fn get_aliases(
+    file: &str,
+    input_dir: &str,
+    metadata: &serde_yaml::Value,
+    url: &str,
+) -> Result<Vec<Alias>> {
+    let name = utils::get_source_name(file, input_dir);
+    let aliases = match metadata.get("aliases") {
+        Some(serde_yaml::Value::Sequence(aliases)) => aliases,
+        Some(_) => {
+            return Err(anyhow!(
+                "Invalid aliases in {}, expected a list of paths",
+                name
+            ))
+        }
+        None => return Ok(vec![]),
+    };
+
+    aliases
+        .iter()
+        .map(|alias| {
+            let alias = alias
+                .as_str()
+                .filter(|x| !x.trim().is_empty())
+                .ok_or_else(|| anyhow!("Invalid alias in {}, expected a path", name))?
+                .trim();
+
+            if alias.split('/').any(|x| x == "." || x == "..") {
+                return Err(anyhow!(
+                    "Invalid alias `{}` in {}, it can't contain `.` or `..`",
+                    alias,
+                    name
+                ));
+            }
+
+            Ok(Alias {
+                file: file.to_string(),
+                from: format!("/{}", alias.trim_start_matches('/')),
+                to: url.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Output file of an alias, `/old.html` is kept as is and other paths get an index file
+pub fn get_alias_file_path(output_dir: &str, alias: &Alias) -> String {
+    if alias.from.ends_with(".html") {
+        format!("{}{}", output_dir, alias.from)
+    } else {
+        format!(
+            "{}{}/index.html",
+            output_dir,
+            alias.from.trim_end_matches('/')
+        )
+    }
+}
+
+pub fn generate_redirect_page(settings: &Settings, url: &str) -> String {
+    let canonical_url = settings
+        .get_permalink(url)
+        .unwrap_or_else(|| url.to_string());
+
+    let mut escaped_url = String::new();
+    let _ = pulldown_cmark::escape::escape_html(&mut escaped_url, url);
+    let mut escaped_canonical_url = String::new();
+    let _ = pulldown_cmark::escape::escape_html(&mut escaped_canonical_url, &canonical_url);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>Redirecting…</title>
+<link rel="canonical" href="{canonical}">
+<meta name="robots" content="noindex">
+<meta http-equiv="refresh" content="0; url={url}">
+</head>
+<body>
+<p>This page has moved to <a href="{url}">{url}</a>.</p>
+</body>
+</html>
+"#,
+        canonical = escaped_canonical_url,
+        url = escaped_url
+    )
+}
+
+// `_redirects` file as used by Netlify and Cloudflare Pages
+pub fn generate_redirects_file(aliases: &[Alias], base_path: &str) -> String {
+    aliases
+        .iter()
+        .map(|alias| format!("{}{} {} 301\n", base_path, alias.from, alias.to))
+        .collect()
+}
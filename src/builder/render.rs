@@ -1,25 +1,35 @@
-use std::{fs, path::Path};
+use std::{collections::HashMap, fs};
 
-use super::seo;
+use super::{seo, theme, BuildContext, SiteDirectory};
 use crate::shared::{
     logger::Logger,
     settings::{self, Link},
     utils,
 };
 
-use super::{cache, handlebar_helpers};
+use super::{cache, front_matter, gallery, handlebar_helpers, images, markdown, math, shortcodes};
 use anyhow::{Context, Result};
 use handlebars::Handlebars;
 use rayon::prelude::*;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use walkdir::WalkDir;
+
+pub const DEFAULT_LAYOUT: &str = "app";
 
 pub struct Render<'a> {
     file: String,
-    theme_dir: String,
-    settings: settings::Settings,
+    // Path of the file relative to the site, used in messages
+    name: String,
+    input_dir: &'a str,
+    theme_dirs: &'a [String],
+    settings: &'a settings::Settings,
+    theme: &'a serde_yaml::Value,
     cache: Option<cache::Cache>,
-    handlebars: Handlebars<'a>,
+    images: images::ImageProcessor,
+    defaults: serde_yaml::Value,
+    handlebars: &'a Handlebars<'static>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,38 +44,41 @@ struct AppRenderData {
     page_metadata: Option<serde_yaml::Value>,
     data: Option<toml::Value>,
     remote_data: serde_json::Value,
+    theme: serde_yaml::Value,
 }
 
 #[derive(Serialize, Deserialize)]
 struct PageRenderData {
     body: String,
     root: serde_yaml::Value,
+    pages: serde_yaml::Value,
     data: serde_yaml::Value,
     remote_data: serde_json::Value,
+    theme: serde_yaml::Value,
+    gallery: Vec<gallery::GalleryImage>,
+    word_count: usize,
+    reading_time: usize,
 }
 
-impl Render<'_> {
+impl<'a> Render<'a> {
     pub fn new(
         file: &str,
-        theme_dir: &str,
-        settings: settings::Settings,
+        context: &'a BuildContext,
         cache: Option<cache::Cache>,
+        images: images::ImageProcessor,
+        defaults: serde_yaml::Value,
     ) -> Self {
-        let mut handlebars = Handlebars::new();
-        handlebars.register_helper("slice", Box::new(handlebar_helpers::SliceHelper));
-        handlebars.register_helper("stringify", Box::new(handlebar_helpers::StringifyHelper));
-        handlebars.register_helper("sort-by", Box::new(handlebar_helpers::SortByHelper));
-        handlebars.register_helper(
-            "format-date",
-            Box::new(handlebar_helpers::DateFormaterHelper),
-        );
-
         Self {
             file: file.to_string(),
-            theme_dir: theme_dir.to_string(),
-            settings,
+            name: utils::get_source_name(file, &context.input_dir),
+            input_dir: &context.input_dir,
+            theme_dirs: &context.theme_dirs,
+            settings: &context.settings,
+            theme: &context.theme_params,
             cache,
-            handlebars,
+            images,
+            defaults,
+            handlebars: &context.handlebars,
         }
     }
 
@@ -73,17 +86,11 @@ impl Render<'_> {
         &self,
         template_name: &str,
         url_path: &str,
-        site_directory: &serde_yaml::Value,
+        amp_url_path: &str,
+        site_directory: &SiteDirectory,
     ) -> Result<String> {
         let (metadata, markdown) = self.get_markdown_and_metadata()?;
 
-        let metadata = if let Some(metadata) = metadata {
-            let metadata = utils::parse_string_to_yaml(&metadata)?;
-            Some(metadata)
-        } else {
-            None
-        };
-
         // Check if the page is expected to have an amp version
         let is_amp_template = template_name == "amp";
         let is_amp = metadata
@@ -95,70 +102,136 @@ impl Render<'_> {
             return Ok(String::new());
         }
 
+        // AMP pages keep the `amp` template whatever their layout
+        let layout = match &metadata {
+            Some(metadata) if !is_amp_template => get_layout(&self.name, metadata)?,
+            _ => template_name,
+        }
+        .to_string();
+
         let content = if let Some(metadata) = &metadata {
-            self.render_body(&markdown, metadata, site_directory)
-                .with_context(|| format!("Failed to render page: {}", self.file))?
+            self.render_body(&markdown, metadata, site_directory)?
         } else {
             markdown
         };
 
         let html = self
-            .handlebars
             .render_template(
-                &self
-                    .get_template(template_name)
-                    .context("Failed to get template")?,
+                &layout,
                 &AppRenderData {
                     title: self.settings.meta.title.clone(),
                     description: self.settings.meta.description.clone(),
                     open_graph_tags: seo::generate_open_graph_tags(
-                        &self.settings,
+                        self.settings,
                         url_path,
+                        amp_url_path,
                         is_amp,
                         is_amp_template,
                     )?,
                     content,
                     styles: self.get_global_styles()?,
                     scripts: self.get_global_scripts()?,
-                    links: self.settings.navigation.links.clone(),
+                    links: self
+                        .settings
+                        .navigation
+                        .links
+                        .iter()
+                        .map(|link| Link {
+                            label: link.label.clone(),
+                            url: self.settings.get_build_settings().prefix_url(&link.url),
+                        })
+                        .collect(),
                     page_metadata: metadata,
                     data: self.settings.data.clone(),
                     remote_data: self.get_remote_data()?,
+                    theme: self.theme.clone(),
                 },
             )
-            .context("Failed to render page")?;
+            .map_err(|e| anyhow::anyhow!("Failed to render page {}, {}", self.name, e))?;
 
         Ok(html)
     }
 
-    pub fn get_metadata(&self) -> Result<Option<String>> {
-        let markdown = fs::read_to_string(&self.file)?;
+    // Front matter of the page for the site directory, with computed fields
+    pub fn get_directory_entry(&self) -> Result<serde_yaml::Value> {
+        let (metadata, body, first_line) = self.get_markdown_source()?;
+        let summary_line = metadata
+            .as_ref()
+            .and_then(|x| x.get_key_line("summary"))
+            .unwrap_or(1);
 
-        let metadata = Regex::new(r"^(?s)---(.*?)---")
-            .context("Failed to parse metadata from markdown file")?;
+        let mut metadata = match self.parse_metadata(metadata)? {
+            Some(metadata) => metadata,
+            None => return Ok(serde_yaml::Value::Null),
+        };
 
-        if let Some(captures) = metadata.captures(&markdown) {
-            let metadata = captures
-                .get(1)
-                .with_context(|| format!("Failed to get metadata from captures: {}", self.file))?
-                .as_str();
+        // Rendering errors are reported when the page itself is processed
+        let math = is_math_enabled(&metadata);
+        let text = match self.render_markdown(&body, first_line, math) {
+            Ok(html) => markdown::html_to_text(&html),
+            Err(_) => return Ok(metadata),
+        };
 
-            Ok(Some(metadata.to_string()))
-        } else {
-            Ok(None)
+        if let serde_yaml::Value::Mapping(entry) = &mut metadata {
+            let (word_count, reading_time) = self.get_reading_stats(&text);
+            entry.insert("word_count".into(), word_count.into());
+            entry.insert("reading_time".into(), reading_time.into());
+
+            let (summary, summary_text) =
+                self.get_summary(entry, &body, first_line, summary_line, math, &text)?;
+            entry.insert("summary".into(), summary.into());
+            entry.insert("summary_text".into(), summary_text.into());
         }
+
+        Ok(metadata)
     }
 
-    fn get_template(&self, name: &str) -> Result<String> {
-        let template = fs::read_to_string(format!("{}/{}.hbs", self.theme_dir, name))?;
+    // Renders the markdown of the page and its summary, which resolves shortcodes and math
+    pub fn check_body(&self) -> Result<()> {
+        self.get_markdown_and_metadata()?;
+        self.get_directory_entry()?;
 
-        Ok(template)
+        Ok(())
     }
 
-    fn get_global_styles(&self) -> Result<String> {
-        let css_path = format!("{}/global.css", self.theme_dir);
+    // Renders a theme template by name, errors point to the template file and position
+    fn render_template<T: Serialize>(&self, name: &str, data: &T) -> Result<String> {
+        if !self.handlebars.has_template(name) {
+            return Err(anyhow::anyhow!(
+                "template {}.hbs doesn't exist in the theme",
+                name
+            ));
+        }
 
-        let styles = if Path::new(&css_path).exists() {
+        // Helpers like `responsive-image` resolve paths against the page being rendered
+        let mut data = serde_json::to_value(data)?;
+        if let serde_json::Value::Object(data) = &mut data {
+            data.insert(
+                handlebar_helpers::PAGE_FILE_KEY.to_string(),
+                self.file.clone().into(),
+            );
+        }
+
+        self.handlebars
+            .render_with_context(name, &handlebars::Context::from(data))
+            .map_err(|e| {
+                let position = match (e.line_no, e.column_no) {
+                    (Some(line), Some(column)) => format!(":{}:{}", line, column),
+                    _ => String::new(),
+                };
+
+                // The template may come from a parent theme
+                let template = format!("{}.hbs", e.template_name.as_deref().unwrap_or(name));
+                let template_file = theme::find_file(self.theme_dirs, &template)
+                    .map(|x| utils::get_source_name(&x.display().to_string(), self.input_dir))
+                    .unwrap_or(template);
+
+                anyhow::anyhow!("{}{}, {}", template_file, position, e.desc)
+            })
+    }
+
+    fn get_global_styles(&self) -> Result<String> {
+        let styles = if let Some(css_path) = theme::find_file(self.theme_dirs, "global.css") {
             fs::read_to_string(css_path)?
         } else {
             String::new()
@@ -208,40 +281,244 @@ impl Render<'_> {
         Ok(script_tag)
     }
 
-    fn get_markdown_and_metadata(&self) -> Result<(Option<String>, String)> {
+    // Front matter, markdown body and the line the body starts on
+    fn get_markdown_source(&self) -> Result<(Option<front_matter::FrontMatter>, String, usize)> {
         let markdown = fs::read_to_string(&self.file)?;
+        let (front_matter, body, first_line) = front_matter::split(&markdown);
+
+        Ok((front_matter, body.to_string(), first_line))
+    }
+
+    // Front matter of the page merged over its directory defaults
+    pub fn get_metadata(&self) -> Result<Option<serde_yaml::Value>> {
+        let (metadata, _, _) = self.get_markdown_source()?;
+
+        self.parse_metadata(metadata)
+    }
+
+    fn parse_metadata(
+        &self,
+        front_matter: Option<front_matter::FrontMatter>,
+    ) -> Result<Option<serde_yaml::Value>> {
+        let metadata = front_matter
+            .map(|x| x.parse(&self.name))
+            .transpose()?
+            .filter(|x| !x.is_null());
+
+        Ok(match (&self.defaults, metadata) {
+            (serde_yaml::Value::Null, metadata) => metadata,
+            (defaults, Some(metadata)) => {
+                Some(utils::merge_yaml_values(defaults.clone(), metadata))
+            }
+            (defaults, None) => Some(defaults.clone()),
+        })
+    }
+
+    fn get_markdown_and_metadata(&self) -> Result<(Option<serde_yaml::Value>, String)> {
+        let (metadata, body, first_line) = self.get_markdown_source()?;
 
-        let metadata = Regex::new(r"^(?s)---(.*?)---(.*)")
-            .context("Failed to parse metadata from markdown file")?;
+        let metadata = self.parse_metadata(metadata)?;
+        let math = metadata.as_ref().is_some_and(is_math_enabled);
 
-        if let Some(captures) = metadata.captures(&markdown) {
-            let metadata = captures
-                .get(1)
-                .with_context(|| format!("Failed to get metadata from captures: {}", self.file))?
-                .as_str();
-            let markdown = captures
-                .get(2)
-                .with_context(|| format!("Failed to get markdown from captures: {}", self.file))?
-                .as_str();
+        let html = self.render_markdown(&body, first_line, math)?;
 
-            Ok((Some(metadata.to_string()), self.markdown_to_html(markdown)))
+        Ok((metadata, html))
+    }
+
+    // Front matter `summary`, the content before `<!-- more -->` or the first words
+    // of the page, as HTML and plain text
+    fn get_summary(
+        &self,
+        metadata: &serde_yaml::Mapping,
+        body: &str,
+        first_line: usize,
+        summary_line: usize,
+        math: bool,
+        text: &str,
+    ) -> Result<(String, String)> {
+        let more = Regex::new(r"<!--\s*more\s*-->").context("Failed to parse more marker")?;
+
+        let summary = if let Some(summary) = metadata.get("summary").and_then(|x| x.as_str()) {
+            self.render_markdown(summary, summary_line, math)?
+        } else if let Some(marker) = more.find(body) {
+            self.render_markdown(&body[..marker.start()], first_line, math)?
+        } else {
+            let length = self.settings.get_site_settings().get_summary_length();
+
+            let words = text.split_whitespace().collect::<Vec<&str>>();
+            let mut summary = words
+                .iter()
+                .take(length)
+                .copied()
+                .collect::<Vec<&str>>()
+                .join(" ");
+            if words.len() > length {
+                summary.push('…');
+            }
+
+            let mut html = String::from("<p>");
+            let _ = pulldown_cmark::escape::escape_html(&mut html, &summary);
+            html.push_str("</p>\n");
+            html
+        };
+
+        let summary_text = markdown::html_to_text(&summary)
+            .split_whitespace()
+            .collect::<Vec<&str>>()
+            .join(" ");
+
+        Ok((summary, summary_text))
+    }
+
+    // Word count and reading time in minutes of the rendered text
+    fn get_reading_stats(&self, text: &str) -> (usize, usize) {
+        let word_count = markdown::count_words(text);
+        let words_per_minute = self.settings.get_site_settings().get_words_per_minute();
+
+        (word_count, word_count.div_ceil(words_per_minute))
+    }
+
+    fn render_markdown(&self, markdown: &str, first_line: usize, math: bool) -> Result<String> {
+        let mut source = String::new();
+        let mut rendered = vec![];
+
+        let blocks = if self.settings.get_site_settings().container_callouts() {
+            markdown::parse_containers(markdown, first_line)
         } else {
-            Ok((None, self.markdown_to_html(&markdown)))
+            vec![markdown::Block::Text(markdown.to_string(), first_line)]
+        };
+
+        for block in blocks {
+            match block {
+                markdown::Block::Text(text, first_line) => {
+                    // Code is found over the whole block, shortcodes can split it
+                    let code = if math {
+                        math::get_code_ranges(&text)
+                    } else {
+                        vec![]
+                    };
+
+                    for node in shortcodes::parse(&text, &self.name, first_line)? {
+                        match node {
+                            shortcodes::Node::Text(text, _) if !math => source.push_str(&text),
+                            shortcodes::Node::Text(piece, offset) => {
+                                let range = offset..offset + piece.len();
+                                for node in math::parse(&text, range, first_line, &code) {
+                                    match node {
+                                        math::Node::Text(text) => source.push_str(&text),
+                                        math::Node::Math(math) => {
+                                            source
+                                                .push_str(&shortcodes::placeholder(rendered.len()));
+                                            rendered.push(math.to_mathml(&self.name)?);
+                                        }
+                                    }
+                                }
+                            }
+                            shortcodes::Node::Escaped(text) => source.push_str(&text),
+                            shortcodes::Node::Shortcode(shortcode) => {
+                                source.push_str(&shortcodes::placeholder(rendered.len()));
+                                rendered.push(self.render_shortcode(shortcode, math)?);
+                            }
+                        }
+                    }
+                }
+                markdown::Block::Callout(callout, content, first_line) => {
+                    let body = self.render_markdown(&content, first_line, math)?;
+                    source.push_str(&format!(
+                        "\n\n{}\n\n",
+                        shortcodes::placeholder(rendered.len())
+                    ));
+                    rendered.push(self.render_callout(
+                        callout,
+                        body,
+                        &self.get_markdown_hooks()?,
+                    )?);
+                }
+            }
+        }
+
+        let html = self.markdown_to_html(&source)?;
+
+        Ok(shortcodes::restore_placeholders(&html, &rendered))
+    }
+
+    fn render_shortcode(&self, shortcode: shortcodes::Shortcode, math: bool) -> Result<String> {
+        let template = format!("shortcodes/{}", shortcode.name);
+        if !self.handlebars.has_template(&template) {
+            return Err(anyhow::anyhow!(
+                "Unknown shortcode `{}` at {}:{}",
+                shortcode.name,
+                self.name,
+                shortcode.line
+            ));
         }
+
+        let mut data = shortcode.params;
+        if let Some((inner, first_line)) = shortcode.inner {
+            let inner = self.render_markdown(&inner, first_line, math)?;
+            data.insert("inner".to_string(), serde_json::Value::String(inner));
+        }
+
+        self.render_template(&template, &data).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to render shortcode `{}` at {}:{}, {}",
+                shortcode.name,
+                self.name,
+                shortcode.line,
+                e
+            )
+        })
     }
 
-    fn markdown_to_html(&self, markdown: &str) -> String {
+    fn markdown_to_html(&self, markdown: &str) -> Result<String> {
         let parser = pulldown_cmark::Parser::new_ext(markdown, pulldown_cmark::Options::all());
         let mut content = String::new();
 
-        if self
+        let external_links_open_in_new_tab = self
             .settings
             .get_site_settings()
-            .external_links_open_in_new_tab()
-        {
-            let events = parser.map(|event| match event {
+            .external_links_open_in_new_tab();
+        let process_images = self.images.is_enabled();
+        let hooks = self.get_markdown_hooks()?;
+
+        let mut events = vec![];
+        // Elements being collected for a render hook, innermost last
+        let mut elements: Vec<(markdown::Element, Vec<pulldown_cmark::Event>)> = vec![];
+
+        for event in parser {
+            if let pulldown_cmark::Event::Start(tag) = &event {
+                if let Some(element) = markdown::Element::from_tag(tag) {
+                    let has_hook = hooks.contains_key(element.hook_name());
+                    let is_collected = match element {
+                        markdown::Element::Image { .. } => has_hook || process_images,
+                        // Needed to detect callouts
+                        markdown::Element::BlockQuote => true,
+                        _ => has_hook,
+                    };
+                    if is_collected {
+                        elements.push((element, vec![]));
+                        continue;
+                    }
+                }
+            }
+
+            let event = match event {
+                pulldown_cmark::Event::End(tag)
+                    if elements
+                        .last()
+                        .is_some_and(|(element, _)| element.is_end(&tag)) =>
+                {
+                    let (element, inner) = elements.pop().context("Failed to get element")?;
+                    let html = self.render_element(
+                        element,
+                        inner,
+                        &hooks,
+                        external_links_open_in_new_tab,
+                    )?;
+                    pulldown_cmark::Event::Html(html.into())
+                }
                 pulldown_cmark::Event::Start(pulldown_cmark::Tag::Link(_, dest, title))
-                    if dest.starts_with("http://") || dest.starts_with("https://") =>
+                    if external_links_open_in_new_tab && markdown::is_external_url(&dest) =>
                 {
                     let mut anchor = String::from("<a href=\"");
                     let _ = pulldown_cmark::escape::escape_href(&mut anchor, &dest);
@@ -252,46 +529,207 @@ impl Render<'_> {
                     anchor.push_str("\" target=\"_blank\" rel=\"noopener noreferrer\">");
                     pulldown_cmark::Event::Html(anchor.into())
                 }
-                _ => event,
-            });
-            pulldown_cmark::html::push_html(&mut content, events);
-        } else {
-            pulldown_cmark::html::push_html(&mut content, parser);
+                event => event,
+            };
+
+            match elements.last_mut() {
+                Some((_, inner)) => inner.push(event),
+                None => events.push(event),
+            }
+        }
+
+        pulldown_cmark::html::push_html(&mut content, events.into_iter());
+
+        self.prefix_links(&content)
+    }
+
+    // Root relative links in markdown are written against the site root, so they
+    // need the base path when the site is served from a subdirectory
+    fn prefix_links(&self, html: &str) -> Result<String> {
+        let build_settings = self.settings.get_build_settings();
+        if build_settings.get_base_path().is_empty() {
+            return Ok(html.to_string());
         }
 
-        content
+        let pattern =
+            Regex::new(r#"(href|src)="(/[^"]*)""#).context("Failed to parse link pattern")?;
+
+        Ok(pattern
+            .replace_all(html, |captures: &regex::Captures| {
+                format!(
+                    "{}=\"{}\"",
+                    &captures[1],
+                    build_settings.prefix_url(&captures[2])
+                )
+            })
+            .to_string())
+    }
+
+    fn get_markdown_hooks(&self) -> Result<HashMap<&'static str, String>> {
+        let mut hooks = HashMap::new();
+
+        for name in markdown::HOOKS {
+            let hook = format!("_hooks/{}", name);
+            if self.handlebars.has_template(&hook) {
+                hooks.insert(name, hook);
+            }
+        }
+
+        Ok(hooks)
+    }
+
+    fn render_element(
+        &self,
+        element: markdown::Element,
+        inner: Vec<pulldown_cmark::Event>,
+        hooks: &HashMap<&'static str, String>,
+        external_links_open_in_new_tab: bool,
+    ) -> Result<String> {
+        let hook_name = element.hook_name();
+
+        let data = match element {
+            markdown::Element::Link { destination, title } => {
+                let is_external = markdown::is_external_url(&destination);
+                json!({
+                    "destination": destination,
+                    "title": title,
+                    "text": markdown::events_to_html(inner),
+                    "is_external": is_external,
+                    "open_in_new_tab": is_external && external_links_open_in_new_tab,
+                })
+            }
+            markdown::Element::Image { destination, title } => {
+                let alt = markdown::events_to_text(&inner);
+                let html = if self.images.is_enabled() {
+                    self.images
+                        .render_image(&self.file, &destination, &alt, &title)
+                } else {
+                    images::render_plain_image(&destination, &alt, &title)
+                };
+
+                if !hooks.contains_key(hook_name) {
+                    return Ok(html);
+                }
+
+                json!({
+                    "destination": destination,
+                    "title": title,
+                    "text": alt,
+                    "html": html,
+                })
+            }
+            markdown::Element::Heading { level, id, classes } => json!({
+                "level": level,
+                "id": id,
+                "classes": classes,
+                "text": markdown::events_to_html(inner),
+            }),
+            markdown::Element::CodeBlock { language } => json!({
+                "language": language,
+                "code": markdown::events_to_text(&inner),
+            }),
+            markdown::Element::BlockQuote => {
+                let mut inner = inner;
+                return match markdown::take_callout(&mut inner) {
+                    Some(callout) => {
+                        self.render_callout(callout, markdown::events_to_html(inner), hooks)
+                    }
+                    None => Ok(format!(
+                        "<blockquote>\n{}</blockquote>\n",
+                        markdown::events_to_html(inner)
+                    )),
+                };
+            }
+        };
+
+        let hook = hooks
+            .get(hook_name)
+            .with_context(|| format!("Failed to get {} hook", hook_name))?;
+
+        self.render_template(hook, &data).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to render {} hook for {}, {}",
+                hook_name,
+                self.name,
+                e
+            )
+        })
+    }
+
+    fn render_callout(
+        &self,
+        callout: markdown::Callout,
+        body: String,
+        hooks: &HashMap<&'static str, String>,
+    ) -> Result<String> {
+        match hooks.get("callout") {
+            Some(hook) => self
+                .render_template(
+                    hook,
+                    &json!({
+                        "kind": callout.kind,
+                        "title": callout.title,
+                        "text": body,
+                    }),
+                )
+                .map_err(|e| {
+                    anyhow::anyhow!("Failed to render callout hook for {}, {}", self.name, e)
+                }),
+            None => {
+                let mut html = format!(
+                    "<aside class=\"callout callout-{}\">\n<p class=\"callout-title\">",
+                    callout.kind
+                );
+                let _ = pulldown_cmark::escape::escape_html(&mut html, &callout.title);
+                html.push_str(&format!("</p>\n{}</aside>\n", body));
+                Ok(html)
+            }
+        }
     }
 
     fn render_body(
         &self,
         body: &str,
         metadata: &serde_yaml::Value,
-        site_directory: &serde_yaml::Value,
+        site_directory: &SiteDirectory,
     ) -> Result<String> {
         let template = if let Some(template) = metadata.get("template") {
             let template = template
                 .as_str()
-                .with_context(|| format!("Failed to get template from metadata: {}", self.file))?;
+                .with_context(|| format!("Failed to get template from metadata: {}", self.name))?;
 
-            let page_render_data = if let Some(data) = self.settings.get_data_yaml()? {
-                PageRenderData {
-                    body: body.to_string(),
-                    root: site_directory.clone(),
-                    data: utils::merge_yaml_values(data, metadata.clone()),
-                    remote_data: self.get_remote_data()?,
-                }
+            let data = if let Some(data) = self.settings.get_data_yaml()? {
+                utils::merge_yaml_values(data, metadata.clone())
             } else {
-                PageRenderData {
-                    body: body.to_string(),
-                    data: metadata.clone(),
-                    root: site_directory.clone(),
-                    remote_data: self.get_remote_data()?,
-                }
+                metadata.clone()
+            };
+
+            let gallery = if let Some(gallery_dir) = metadata.get("gallery") {
+                let gallery_dir = gallery_dir.as_str().with_context(|| {
+                    format!("Failed to get gallery from metadata: {}", self.name)
+                })?;
+                gallery::get_gallery(&self.images, &self.file, gallery_dir)?
+            } else {
+                vec![]
+            };
+
+            let (word_count, reading_time) = self.get_reading_stats(&markdown::html_to_text(body));
+
+            let page_render_data = PageRenderData {
+                body: body.to_string(),
+                root: site_directory.root.clone(),
+                pages: site_directory.pages.clone(),
+                data,
+                remote_data: self.get_remote_data()?,
+                theme: self.theme.clone(),
+                gallery,
+                word_count,
+                reading_time,
             };
 
             let body = self
-                .handlebars
-                .render_template(&self.get_template(template)?, &page_render_data)?;
+                .render_template(template, &page_render_data)
+                .map_err(|e| anyhow::anyhow!("Failed to render page {}, {}", self.name, e))?;
 
             Ok(body)
         } else {
@@ -331,3 +769,95 @@ impl Render<'_> {
         }
     }
 }
+
+fn is_math_enabled(metadata: &serde_yaml::Value) -> bool {
+    metadata
+        .get("math")
+        .and_then(|math| math.as_bool())
+        .unwrap_or(false)
+}
+
+// Outer template of the page, `layout` front matter or `app`
+pub fn get_layout<'a>(file: &str, metadata: &'a serde_yaml::Value) -> Result<&'a str> {
+    match metadata.get("layout") {
+        Some(layout) => layout
+            .as_str()
+            .filter(|x| !x.trim().is_empty())
+            .with_context(|| format!("Invalid layout in {}, expected a template name", file)),
+        None => Ok(DEFAULT_LAYOUT),
+    }
+}
+
+// Handlebars registry shared by every page of a build, with the helpers and
+// without templates, see `register_templates`
+pub fn get_handlebars(
+    settings: &settings::Settings,
+    images: images::ImageProcessor,
+) -> Handlebars<'static> {
+    let mut handlebars = Handlebars::new();
+    handlebars.set_strict_mode(settings.get_build_settings().has_strict_templates());
+    handlebars.register_helper("slice", Box::new(handlebar_helpers::SliceHelper));
+    handlebars.register_helper("stringify", Box::new(handlebar_helpers::StringifyHelper));
+    handlebars.register_helper("sort-by", Box::new(handlebar_helpers::SortByHelper));
+    handlebars.register_helper(
+        "format-date",
+        Box::new(handlebar_helpers::DateFormaterHelper),
+    );
+    handlebars.register_helper(
+        "url_for",
+        Box::new(handlebar_helpers::UrlForHelper {
+            settings: settings.get_build_settings(),
+        }),
+    );
+    // Same as `url_for`, named after the Jekyll filter
+    handlebars.register_helper(
+        "relative_url",
+        Box::new(handlebar_helpers::UrlForHelper {
+            settings: settings.get_build_settings(),
+        }),
+    );
+    handlebars.register_helper(
+        "responsive-image",
+        Box::new(handlebar_helpers::ResponsiveImageHelper { images }),
+    );
+
+    handlebars
+}
+
+// Registers every `.hbs` file of the theme by its path without the extension, like
+// `post` or `partials/header`, so that templates can be used as partials. Parent
+// themes are registered first so that the theme's own files replace theirs
+pub fn register_templates(
+    handlebars: &mut Handlebars,
+    theme_dirs: &[String],
+) -> Vec<(String, handlebars::TemplateError)> {
+    theme_dirs
+        .iter()
+        .rev()
+        .flat_map(|theme_dir| register_theme_templates(handlebars, theme_dir))
+        .collect()
+}
+
+fn register_theme_templates(
+    handlebars: &mut Handlebars,
+    theme_dir: &str,
+) -> Vec<(String, handlebars::TemplateError)> {
+    WalkDir::new(theme_dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let file = e.path().display().to_string();
+            let name = file
+                .strip_prefix(theme_dir)?
+                .trim_start_matches('/')
+                .strip_suffix(".hbs")?
+                .to_string();
+
+            handlebars
+                .register_template_file(&name, e.path())
+                .err()
+                .map(|e| (file, e))
+        })
+        .collect()
+}
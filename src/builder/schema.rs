@@ -0,0 +1,259 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+use serde_yaml::Value;
+
+pub const SCHEMAS_DIR: &str = "schemas";
+
+// Front matter RustyInk itself understands, and the fields it computes for the
+// site directory, which are never reported as unknown
+const BUILTIN_KEYS: [&str; 21] = [
+    "template",
+    "layout",
+    "title",
+    "description",
+    "amp",
+    "math",
+    "gallery",
+    "summary",
+    "slug",
+    "permalink",
+    "aliases",
+    "cascade",
+    "word_count",
+    "reading_time",
+    "summary_text",
+    "url",
+    "source_path",
+    "section",
+    "is_section",
+    "date_modified",
+    "has_amp",
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    #[serde(default)]
+    fields: BTreeMap<String, Field>,
+    // Fields that aren't declared are reported unless this is set
+    #[serde(default)]
+    allow_unknown: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Field {
+    #[serde(rename = "type")]
+    kind: Option<FieldType>,
+    #[serde(default)]
+    required: bool,
+    #[serde(rename = "enum")]
+    values: Option<Vec<Value>>,
+    // chrono format of `date` fields, like `%Y-%m-%d`
+    format: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    List,
+    Map,
+    Date,
+}
+
+impl FieldType {
+    fn name(&self) -> &'static str {
+        match self {
+            FieldType::String => "a string",
+            FieldType::Number => "a number",
+            FieldType::Integer => "an integer",
+            FieldType::Boolean => "a boolean",
+            FieldType::List => "a list",
+            FieldType::Map => "a mapping",
+            FieldType::Date => "a date",
+        }
+    }
+}
+
+// Schemas of the theme and its parents by template name, a theme's schema
+// replaces the one of its parent
+pub fn read_schemas(theme_dirs: &[String]) -> Result<BTreeMap<String, (String, Schema)>> {
+    let mut schemas = BTreeMap::new();
+
+    for theme_dir in theme_dirs.iter().rev() {
+        let schemas_dir = Path::new(theme_dir).join(SCHEMAS_DIR);
+        if schemas_dir.is_dir() {
+            read_schemas_dir(&schemas_dir, &mut schemas)?;
+        }
+    }
+
+    Ok(schemas)
+}
+
+fn read_schemas_dir(
+    schemas_dir: &Path,
+    schemas: &mut BTreeMap<String, (String, Schema)>,
+) -> Result<()> {
+    for entry in fs::read_dir(schemas_dir)? {
+        let path = entry?.path();
+        let template = match path.file_stem() {
+            Some(name) if path.extension().is_some_and(|x| x == "yaml" || x == "yml") => {
+                name.to_string_lossy().to_string()
+            }
+            _ => continue,
+        };
+
+        let file = path.display().to_string();
+        let schema = serde_yaml::from_str::<Schema>(&fs::read_to_string(&path)?)
+            .map_err(|e| anyhow!("Invalid schema {}, {}", file, e))?;
+
+        schemas.insert(template, (file, schema));
+    }
+
+    Ok(())
+}
+
+impl Schema {
+    // Every way the front matter doesn't match the schema
+    pub fn validate(&self, metadata: &Value) -> Vec<String> {
+        let mut violations = vec![];
+
+        for (key, field) in &self.fields {
+            match metadata.get(key).filter(|x| !x.is_null()) {
+                Some(value) => violations.extend(field.validate(key, value)),
+                None if field.required => violations.push(format!("`{}` is required", key)),
+                None => {}
+            }
+        }
+
+        if !self.allow_unknown {
+            if let Value::Mapping(metadata) = metadata {
+                for key in metadata.keys().filter_map(|x| x.as_str()) {
+                    if self.fields.contains_key(key) || BUILTIN_KEYS.contains(&key) {
+                        continue;
+                    }
+
+                    match self.get_suggestion(key) {
+                        Some(suggestion) => violations.push(format!(
+                            "unknown field `{}`, did you mean `{}`?",
+                            key, suggestion
+                        )),
+                        None => violations.push(format!("unknown field `{}`", key)),
+                    }
+                }
+            }
+        }
+
+        violations
+    }
+
+    // Closest declared field, for typos like `autor`
+    fn get_suggestion(&self, key: &str) -> Option<&str> {
+        self.fields
+            .keys()
+            .map(|x| (x, get_edit_distance(key, x)))
+            .filter(|(x, distance)| *distance <= (x.chars().count() / 3).max(1))
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(x, _)| x.as_str())
+    }
+}
+
+impl Field {
+    fn validate(&self, key: &str, value: &Value) -> Vec<String> {
+        let mut violations = vec![];
+
+        if let Some(kind) = self.kind {
+            if !self.has_type(kind, value) {
+                let expected = match (&self.format, kind) {
+                    (Some(format), FieldType::Date) => format!("a date formatted as `{}`", format),
+                    _ => kind.name().to_string(),
+                };
+                violations.push(format!(
+                    "`{}` must be {}, got {}",
+                    key,
+                    expected,
+                    display_value(value)
+                ));
+            }
+        }
+
+        if let Some(values) = &self.values {
+            if !values.contains(value) {
+                violations.push(format!(
+                    "`{}` must be one of {}, got {}",
+                    key,
+                    values
+                        .iter()
+                        .map(display_value)
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    display_value(value)
+                ));
+            }
+        }
+
+        violations
+    }
+
+    fn has_type(&self, kind: FieldType, value: &Value) -> bool {
+        match kind {
+            FieldType::String => value.is_string(),
+            FieldType::Number => value.is_number(),
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::Boolean => value.is_bool(),
+            FieldType::List => value.is_sequence(),
+            FieldType::Map => value.is_mapping(),
+            FieldType::Date => match (value, &self.format) {
+                (Value::String(date), Some(format)) => {
+                    NaiveDateTime::parse_from_str(date, format).is_ok()
+                        || NaiveDate::parse_from_str(date, format).is_ok()
+                }
+                (Value::String(date), None) => {
+                    DateTime::parse_from_rfc3339(date).is_ok()
+                        || NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+                }
+                // Timestamps, as used by `format-date`
+                (Value::Number(_), None) => value.is_i64() || value.is_u64(),
+                _ => false,
+            },
+        }
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(x) => format!("`{}`", x),
+        Value::Number(x) => format!("`{}`", x),
+        Value::Bool(x) => format!("`{}`", x),
+        Value::Sequence(_) => "a list".to_string(),
+        Value::Mapping(_) => "a mapping".to_string(),
+        _ => "an empty value".to_string(),
+    }
+}
+
+fn get_edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let cost = usize::from(x != *y);
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
@@ -10,14 +10,14 @@ pub fn generate_robots_txt(settings: &Settings) -> Result<String> {
     let robots = if blocked {
         String::from("User-agent: *\nDisallow: /")
     } else {
-        let sitemap_base_url = settings
-            .meta
-            .get_base_url()
+        let sitemap_url = settings
+            .get_permalink(&format!(
+                "{}/sitemap.xml",
+                settings.get_build_settings().get_base_path()
+            ))
             .context("No sitemap base url found in Settings.toml")?;
 
-        String::from("User-agent: *\nAllow: /\nSitemap: ")
-            + &sitemap_base_url
-            + &String::from("/sitemap.xml")
+        String::from("User-agent: *\nAllow: /\nSitemap: ") + &sitemap_url
     };
 
     Ok(robots)
@@ -25,7 +25,7 @@ pub fn generate_robots_txt(settings: &Settings) -> Result<String> {
 
 pub fn generate_sitemap_xml(
     settings: &Settings,
-    all_url_paths: &Vec<(String, String)>,
+    all_url_paths: &Vec<(String, serde_yaml::Value)>,
 ) -> Result<String> {
     let sitemap_base_url = settings
         .meta
@@ -40,8 +40,14 @@ pub fn generate_sitemap_xml(
 
     let mut urls = vec![];
 
-    for (file, _) in all_url_paths {
-        let canonical_url = format!("{}{}", sitemap_base_url, file);
+    for (file, metadata) in all_url_paths {
+        let url = metadata
+            .get("url")
+            .and_then(|url| url.as_str())
+            .unwrap_or(file);
+        let canonical_url = settings
+            .get_permalink(url)
+            .context("No sitemap base url found in Settings.toml")?;
 
         if let Ok(canonical_url) = canonical_url.parse() {
             urls.push(UrlEntry {
@@ -61,15 +67,17 @@ pub fn generate_sitemap_xml(
 pub fn generate_open_graph_tags(
     settings: &Settings,
     url_path: &str,
+    amp_url_path: &str,
     is_amp: bool,
     is_amp_template: bool,
 ) -> Result<String> {
     let title = settings.meta.title.clone();
     let description = settings.meta.description.clone();
-    let base_url = settings
-        .meta
-        .get_base_url()
-        .context("No base url found in Settings.toml")?;
+    let permalink = |url: &str| {
+        settings
+            .get_permalink(url)
+            .context("No base url found in Settings.toml")
+    };
 
     let mut tags = vec![];
 
@@ -90,14 +98,14 @@ pub fn generate_open_graph_tags(
 
     if is_amp && !is_amp_template {
         tags.push(format!(
-            "<link rel=\"amphtml\" href=\"{}{}amp\">",
-            base_url, url_path
+            "<link rel=\"amphtml\" href=\"{}\">",
+            permalink(amp_url_path)?
         ));
     }
 
     tags.push(format!(
-        "<link rel=\"canonical\" href=\"{}{}\">",
-        base_url, url_path
+        "<link rel=\"canonical\" href=\"{}\">",
+        permalink(url_path)?
     ));
 
     // Open Graph / Facebook
@@ -0,0 +1,203 @@
+use anyhow::{anyhow, Result};
+use serde_json::{Map, Value as Json};
+
+const OPEN: &str = "{{<";
+const CLOSE: &str = ">}}";
+
+pub enum Node {
+    // Markdown and its offset in the source
+    Text(String, usize),
+    // `{{</* name */>}}` written out as a literal `{{< name >}}`
+    Escaped(String),
+    Shortcode(Shortcode),
+}
+
+pub struct Shortcode {
+    pub name: String,
+    pub params: Map<String, Json>,
+    pub line: usize,
+    // Raw markdown between the opening and closing tags, with its first line
+    pub inner: Option<(String, usize)>,
+}
+
+struct Tag {
+    start: usize,
+    end: usize,
+    name: String,
+    params: Map<String, Json>,
+    is_closing: bool,
+    // `{{</* name */>}}` is written out as a literal `{{< name >}}`
+    escaped: Option<String>,
+}
+
+// Placeholders are plain words so that they survive markdown rendering untouched
+pub fn placeholder(index: usize) -> String {
+    format!("RUSTYINKSHORTCODE{}END", index)
+}
+
+pub fn restore_placeholders(html: &str, rendered: &[String]) -> String {
+    let mut html = html.to_string();
+
+    for (index, shortcode) in rendered.iter().enumerate() {
+        let placeholder = placeholder(index);
+        // Block shortcodes shouldn't end up wrapped in a paragraph
+        html = html
+            .replace(&format!("<p>{}</p>", placeholder), shortcode)
+            .replace(&placeholder, shortcode);
+    }
+
+    html
+}
+
+pub fn parse(source: &str, file: &str, first_line: usize) -> Result<Vec<Node>> {
+    let line_at = |offset: usize| first_line + source[..offset].matches('\n').count();
+    let location = |offset: usize| format!("{}:{}", file, line_at(offset));
+
+    let mut nodes = vec![];
+    let mut position = 0;
+
+    while let Some(tag) = next_tag(source, position, &location)? {
+        nodes.push(Node::Text(
+            source[position..tag.start].to_string(),
+            position,
+        ));
+
+        if let Some(escaped) = tag.escaped {
+            nodes.push(Node::Escaped(escaped));
+            position = tag.end;
+            continue;
+        }
+
+        if tag.is_closing {
+            return Err(anyhow!(
+                "Unexpected closing shortcode `{}` at {}",
+                tag.name,
+                location(tag.start)
+            ));
+        }
+
+        // A shortcode is paired when a matching closing tag follows it
+        let mut depth = 0;
+        let mut closing = None;
+        let mut search = tag.end;
+        while let Some(other) = next_tag(source, search, &location)? {
+            search = other.end;
+
+            if other.escaped.is_some() || other.name != tag.name {
+                continue;
+            }
+
+            if !other.is_closing {
+                depth += 1;
+            } else if depth == 0 {
+                closing = Some(other);
+                break;
+            } else {
+                depth -= 1;
+            }
+        }
+
+        let inner = closing
+            .as_ref()
+            .map(|x| (source[tag.end..x.start].to_string(), line_at(tag.end)));
+
+        nodes.push(Node::Shortcode(Shortcode {
+            name: tag.name,
+            params: tag.params,
+            line: line_at(tag.start),
+            inner,
+        }));
+
+        position = closing.map(|x| x.end).unwrap_or(tag.end);
+    }
+
+    nodes.push(Node::Text(source[position..].to_string(), position));
+
+    Ok(nodes)
+}
+
+fn next_tag(source: &str, from: usize, location: &dyn Fn(usize) -> String) -> Result<Option<Tag>> {
+    let start = match source[from..].find(OPEN) {
+        Some(start) => from + start,
+        None => return Ok(None),
+    };
+
+    let content_start = start + OPEN.len();
+    let content_end = source[content_start..]
+        .find(CLOSE)
+        .map(|x| content_start + x)
+        .ok_or_else(|| anyhow!("Unclosed shortcode at {}", location(start)))?;
+    let end = content_end + CLOSE.len();
+    let content = source[content_start..content_end].trim();
+
+    if let Some(content) = content
+        .strip_prefix("/*")
+        .and_then(|x| x.strip_suffix("*/"))
+    {
+        return Ok(Some(Tag {
+            start,
+            end,
+            name: String::new(),
+            params: Map::new(),
+            is_closing: false,
+            escaped: Some(format!("{} {} {}", OPEN, content.trim(), CLOSE)),
+        }));
+    }
+
+    let (is_closing, content) = match content.strip_prefix('/') {
+        Some(content) => (true, content.trim()),
+        None => (false, content),
+    };
+
+    let (name, params) = match content.split_once(char::is_whitespace) {
+        Some((name, params)) => (name, params),
+        None => (content, ""),
+    };
+
+    if name.is_empty() {
+        return Err(anyhow!("Missing shortcode name at {}", location(start)));
+    }
+
+    let params = parse_params(params)
+        .map_err(|e| anyhow!("{} in shortcode `{}` at {}", e, name, location(start)))?;
+
+    Ok(Some(Tag {
+        start,
+        end,
+        name: name.to_string(),
+        params,
+        is_closing,
+        escaped: None,
+    }))
+}
+
+// Parses `key="value" other=value` pairs
+fn parse_params(params: &str) -> Result<Map<String, Json>> {
+    let mut result = Map::new();
+    let mut rest = params.trim_start();
+
+    while !rest.is_empty() {
+        let (key, value) = rest
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid parameter `{}`", rest))?;
+        let key = key.trim();
+        let value = value.trim_start();
+
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(anyhow!("Invalid parameter `{}`", rest));
+        }
+
+        let (value, remaining) = if let Some(value) = value.strip_prefix('"') {
+            value
+                .split_once('"')
+                .ok_or_else(|| anyhow!("Unclosed quote for parameter `{}`", key))?
+        } else {
+            value.split_once(char::is_whitespace).unwrap_or((value, ""))
+        };
+
+        result.insert(key.to_string(), Json::String(value.to_string()));
+        rest = remaining.trim_start();
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,168 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use super::front_matter;
+use crate::shared::{settings::Settings, utils};
+
+pub const MANIFEST_FILE: &str = "theme.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    // Theme this one falls back to, relative to the theme folder
+    pub parent: Option<String>,
+    // Oldest RustyInk release the theme works with
+    pub min_version: Option<String>,
+    // Templates the theme can't work without, like `app` or `post`
+    #[serde(default)]
+    pub templates: Vec<String>,
+    // Exposed to templates as `theme`, overridable in the `[theme.params]` settings
+    pub params: Option<toml::Value>,
+    // Defaults for `Settings.toml`, which takes precedence
+    pub settings: Option<toml::Table>,
+}
+
+impl Manifest {
+    fn get_name(&self) -> String {
+        match (&self.name, &self.version) {
+            (Some(name), Some(version)) => format!("`{} {}`", name, version),
+            (Some(name), None) => format!("`{}`", name),
+            _ => "The theme".to_string(),
+        }
+    }
+
+    // The theme has to support this release and its templates have to be
+    // provided by the theme or one of its parents
+    pub fn check(&self, theme_dirs: &[String]) -> Result<()> {
+        if let Some(min_version) = &self.min_version {
+            let current_version = env!("CARGO_PKG_VERSION");
+            if parse_version(min_version)? > parse_version(current_version)? {
+                return Err(anyhow!(
+                    "{} requires RustyInk {} or later, this is {}",
+                    self.get_name(),
+                    min_version,
+                    current_version
+                ));
+            }
+        }
+
+        let missing = self
+            .templates
+            .iter()
+            .map(|x| format!("{}.hbs", x))
+            .filter(|x| find_file(theme_dirs, x).is_none())
+            .collect::<Vec<String>>();
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "{} requires templates that are missing from the theme: {}",
+                self.get_name(),
+                missing.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Manifest of the theme, themes without one get an empty manifest
+pub fn read_manifest(theme_dir: &str) -> Result<Manifest> {
+    let manifest_file = Path::new(theme_dir).join(MANIFEST_FILE);
+    if !manifest_file.exists() {
+        return Ok(Manifest::default());
+    }
+
+    let manifest = fs::read_to_string(&manifest_file)?;
+    toml::from_str(&manifest).map_err(|e| {
+        anyhow!(
+            "Invalid theme manifest {}, {}",
+            manifest_file.display(),
+            e.to_string().trim()
+        )
+    })
+}
+
+// The theme folder followed by its parents, from `parent` in the settings or
+// the manifest of each theme
+pub fn get_theme_dirs(theme_dir: &str, parent: Option<&str>) -> Result<Vec<String>> {
+    let mut theme_dirs = vec![theme_dir.to_string()];
+
+    // The settings parent is relative to the site, like the `theme` folder
+    let mut parent = match parent {
+        Some(parent) => Some(
+            Path::new(theme_dir)
+                .parent()
+                .unwrap_or(Path::new(theme_dir))
+                .join(parent),
+        ),
+        None => read_manifest(theme_dir)?
+            .parent
+            .map(|x| Path::new(theme_dir).join(x)),
+    };
+
+    while let Some(dir) = parent {
+        if !dir.is_dir() {
+            return Err(anyhow!("Parent theme {} doesn't exist", dir.display()));
+        }
+
+        let dir = utils::path_to_string(&dir)?;
+        if theme_dirs.contains(&dir) {
+            return Err(anyhow!("Parent themes form a cycle through {}", dir));
+        }
+
+        parent = read_manifest(&dir)?.parent.map(|x| Path::new(&dir).join(x));
+        theme_dirs.push(dir);
+    }
+
+    Ok(theme_dirs)
+}
+
+// First theme in the chain that has the file
+pub fn find_file(theme_dirs: &[String], file: &str) -> Option<PathBuf> {
+    theme_dirs
+        .iter()
+        .map(|x| Path::new(x).join(file))
+        .find(|x| x.exists())
+}
+
+// `theme` template variable, the manifest params merged with the settings overrides
+pub fn get_params(manifests: &[Manifest], settings: &Settings) -> serde_yaml::Value {
+    let mut params = serde_yaml::Value::Null;
+    for manifest in manifests.iter().rev() {
+        if let Some(theme_params) = manifest.params.clone() {
+            params = utils::merge_yaml_values(params, front_matter::toml_to_yaml(theme_params));
+        }
+    }
+
+    let overrides = settings
+        .theme
+        .as_ref()
+        .and_then(|x| x.params.clone())
+        .map(front_matter::toml_to_yaml);
+
+    match overrides {
+        Some(overrides) => utils::merge_yaml_values(params, overrides),
+        None => params,
+    }
+}
+
+fn parse_version(version: &str) -> Result<Vec<u64>> {
+    version
+        .trim()
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|x| {
+            x.parse::<u64>()
+                .with_context(|| format!("Invalid version `{}` in theme manifest", version))
+        })
+        .collect()
+}
@@ -1,110 +1,42 @@
 use std::{
-    format, fs,
-    io::Write,
+    fs,
     path::{Path, PathBuf},
 };
 
 use crate::shared::{logger::Logger, settings::Settings, utils};
 
-use anyhow::{Context, Result};
-use async_recursion::async_recursion;
-use owo_colors::OwoColorize;
-use reqwest::{
-    header::{HeaderMap, USER_AGENT},
-    Client,
-};
-
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-struct GitHubContent {
-    name: String,
-    path: String,
-    #[serde(rename = "type")]
-    r#type: String,
-}
+use anyhow::Result;
+use source::Source;
 
-impl GitHubContent {
-    fn is_file(&self) -> bool {
-        self.r#type == "file"
-    }
-
-    fn is_dir(&self) -> bool {
-        self.r#type == "dir"
-    }
-}
+mod source;
+mod starter;
 
-#[async_recursion]
-async fn download_folder(
-    project_dir: &str,
-    theme: &str,
-    client: &Client,
-    owner: &str,
-    repo: &str,
-    folder_path: &str,
-) -> Result<()> {
-    let url = format!(
-        "https://api.github.com/repos/{}/{}/contents/{}",
-        owner, repo, folder_path
-    );
+pub const DEFAULT_THEME: &str = "pico";
 
-    // Create a custom user agent header to avoid rate limiting
-    let mut headers = HeaderMap::new();
-    headers.insert(USER_AGENT, "RustyInk".parse()?);
-
-    // Send the API request to get the folder contents
-    let response = client
-        .get(&url)
-        .headers(headers)
-        .send()
-        .await?
-        .json::<Vec<GitHubContent>>()
-        .await?;
-
-    for item in response {
-        if item.is_file() {
-            let file_path = item.path.replace(theme, project_dir);
-
-            let folder = Path::new(&file_path)
-                .parent()
-                .context("Failed to get parent folder")?;
-            fs::create_dir_all(folder)?;
-
-            Logger::new().activity(&format!("\tDownloading file: {}", item.path.bold().green()));
+pub async fn project(project_dir: &PathBuf, theme: &str) -> Result<()> {
+    let source = Source::parse(theme)?;
 
-            let download_url = format!(
-                "https://raw.githubusercontent.com/{}/{}/master/{}",
-                owner, repo, item.path
-            );
+    utils::create_dir_in_path(project_dir)?;
 
-            let mut file_response = client.get(download_url).send().await?;
-            let mut file = fs::File::create(file_path)?;
-            while let Some(chunk) = file_response.chunk().await? {
-                file.write_all(&chunk)?;
-            }
-        } else if item.is_dir() {
-            let dir_name = item.name;
-            let subfolder_path = format!("{}/{}", folder_path, dir_name);
+    let project_dir = utils::path_to_string(project_dir)?;
 
-            download_folder(project_dir, theme, client, owner, repo, &subfolder_path).await?;
+    match source.install(&project_dir).await {
+        // Only the default theme, a theme that was asked for is never swapped
+        Err(e) if theme == DEFAULT_THEME && is_network_error(&e) => {
+            Logger::new().warn(&format!(
+                "Failed to download the {} theme ({}), using the built-in starter theme instead",
+                DEFAULT_THEME, e
+            ));
+            starter::write(&project_dir)
         }
+        result => result,
     }
-
-    Ok(())
 }
 
-pub async fn project(project_dir: &PathBuf, theme: &str) -> Result<()> {
-    utils::create_dir_in_path(project_dir)?;
-
-    let project_dir = utils::path_to_string(project_dir)?;
-
-    let client = Client::new();
-    let repo_owner = "arjunkomath";
-    let repo_name = "rustyink-themes";
-
-    Logger::new().activity(&format!("Downloading theme {}", theme.bold().blue()));
-
-    download_folder(&project_dir, theme, &client, repo_owner, repo_name, theme).await?;
-
-    Ok(())
+// Offline or unreachable, as opposed to a theme that doesn't exist
+fn is_network_error(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>()
+        .is_some_and(|x| x.is_connect() || x.is_timeout() || x.is_request())
 }
 
 pub fn settings_file(project_dir: &PathBuf) -> Result<()> {
@@ -0,0 +1,282 @@
+use std::{
+    env, fs,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process,
+};
+
+use anyhow::{anyhow, Context, Result};
+use flate2::read::GzDecoder;
+use fs_extra::dir::CopyOptions;
+use owo_colors::OwoColorize;
+use reqwest::{
+    header::{ACCEPT, AUTHORIZATION, USER_AGENT},
+    Client,
+};
+
+use super::starter;
+use crate::shared::logger::Logger;
+
+// Plain theme names like `pico` are folders of this repo
+const THEMES_OWNER: &str = "arjunkomath";
+const THEMES_REPO: &str = "rustyink-themes";
+const THEMES_REF: &str = "master";
+
+// Used for `gh:` sources, which makes private repos work too
+const TOKEN_VAR: &str = "GITHUB_TOKEN";
+
+#[derive(Clone, Copy)]
+pub enum ArchiveKind {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    fn from_path(path: &str) -> Option<Self> {
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if path.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+pub enum Source {
+    Builtin,
+    Local(PathBuf),
+    Archive {
+        location: String,
+        kind: ArchiveKind,
+        subdir: Option<String>,
+    },
+    GitHub {
+        owner: String,
+        repo: String,
+        reference: Option<String>,
+        subdir: Option<String>,
+    },
+}
+
+impl Source {
+    pub fn parse(theme: &str) -> Result<Self> {
+        if theme == starter::THEME {
+            return Ok(Source::Builtin);
+        }
+
+        if let Some(spec) = theme.strip_prefix("gh:") {
+            return parse_github(spec).with_context(|| {
+                format!(
+                    "Invalid theme `{}`, expected gh:owner/repo#ref:subdir",
+                    theme
+                )
+            });
+        }
+
+        // Archives can be followed by the folder of the theme, like `themes.zip:minimal`
+        let (location, subdir) = match theme.rsplit_once(':') {
+            Some((location, subdir))
+                if ArchiveKind::from_path(theme).is_none()
+                    && ArchiveKind::from_path(location).is_some() =>
+            {
+                (location, Some(subdir.to_string()))
+            }
+            _ => (theme, None),
+        };
+        if let Some(kind) = ArchiveKind::from_path(location) {
+            if !is_url(location) && !Path::new(location).is_file() {
+                return Err(anyhow!("Theme archive {} doesn't exist", location));
+            }
+
+            return Ok(Source::Archive {
+                location: location.to_string(),
+                kind,
+                subdir,
+            });
+        }
+
+        // A local folder wins over a theme of the same name
+        if Path::new(theme).is_dir() {
+            return Ok(Source::Local(PathBuf::from(theme)));
+        }
+
+        if is_name(theme) {
+            return Ok(Source::GitHub {
+                owner: THEMES_OWNER.to_string(),
+                repo: THEMES_REPO.to_string(),
+                reference: Some(THEMES_REF.to_string()),
+                subdir: Some(theme.to_string()),
+            });
+        }
+
+        Err(anyhow!(
+            "Unknown theme `{}`, expected a theme name, a folder, a .tar.gz or .zip archive, or gh:owner/repo#ref:subdir",
+            theme
+        ))
+    }
+
+    pub async fn install(&self, project_dir: &str) -> Result<()> {
+        match self {
+            Source::Builtin => starter::write(project_dir),
+            Source::Local(dir) => {
+                Logger::new().activity(&format!("Copying theme {}", dir.display().bold().blue()));
+                copy_dir(dir, project_dir)
+            }
+            Source::Archive {
+                location,
+                kind,
+                subdir,
+            } => {
+                let archive = if is_url(location) {
+                    Logger::new()
+                        .activity(&format!("Downloading theme {}", location.bold().blue()));
+                    download(Client::new().get(location)).await?
+                } else {
+                    Logger::new().activity(&format!("Extracting theme {}", location.bold().blue()));
+                    fs::read(location)?
+                };
+
+                extract(&archive, *kind, subdir.as_deref(), project_dir)
+            }
+            Source::GitHub {
+                owner,
+                repo,
+                reference,
+                subdir,
+            } => {
+                Logger::new().activity(&format!(
+                    "Downloading theme {}",
+                    format!("{}/{}", owner, repo).bold().blue()
+                ));
+
+                // The whole repo in a single request, the API endpoint is only
+                // needed for the token, it redirects to codeload
+                let client = Client::new();
+                let request = match env::var(TOKEN_VAR).ok().filter(|x| !x.is_empty()) {
+                    Some(token) => client
+                        .get(format!(
+                            "https://api.github.com/repos/{}/{}/tarball/{}",
+                            owner,
+                            repo,
+                            reference.as_deref().unwrap_or_default()
+                        ))
+                        .header(AUTHORIZATION, format!("Bearer {}", token))
+                        .header(ACCEPT, "application/vnd.github+json"),
+                    None => client.get(format!(
+                        "https://codeload.github.com/{}/{}/tar.gz/{}",
+                        owner,
+                        repo,
+                        reference.as_deref().unwrap_or("HEAD")
+                    )),
+                };
+                let archive = download(request.header(USER_AGENT, "RustyInk")).await?;
+
+                extract(&archive, ArchiveKind::TarGz, subdir.as_deref(), project_dir)
+            }
+        }
+    }
+}
+
+// `owner/repo`, optionally followed by `#ref` and `:subdir`
+fn parse_github(spec: &str) -> Result<Source> {
+    let (repo, subdir) = match spec.split_once(':') {
+        Some((repo, subdir)) => (repo, Some(subdir.trim_matches('/').to_string())),
+        None => (spec, None),
+    };
+    let (repo, reference) = match repo.split_once('#') {
+        Some((repo, reference)) => (repo, Some(reference.to_string())),
+        None => (repo, None),
+    };
+    let (owner, repo) = repo.split_once('/').context("Missing repo")?;
+
+    if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+        return Err(anyhow!("Invalid repo `{}/{}`", owner, repo));
+    }
+
+    Ok(Source::GitHub {
+        owner: owner.to_string(),
+        repo: repo.to_string(),
+        reference: reference.filter(|x| !x.is_empty()),
+        subdir: subdir.filter(|x| !x.is_empty()),
+    })
+}
+
+fn is_url(location: &str) -> bool {
+    location.starts_with("https://") || location.starts_with("http://")
+}
+
+// Names of the themes repo, anything with a path separator or a dot has to be a folder
+fn is_name(theme: &str) -> bool {
+    !theme.is_empty()
+        && theme
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_')
+}
+
+async fn download(request: reqwest::RequestBuilder) -> Result<Vec<u8>> {
+    let response = request.send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+// Unpacks the archive in a temporary folder and copies the theme from it,
+// archives with a single top level folder, like GitHub's, are read from inside it
+fn extract(
+    archive: &[u8],
+    kind: ArchiveKind,
+    subdir: Option<&str>,
+    project_dir: &str,
+) -> Result<()> {
+    let temp_dir = env::temp_dir().join(format!("rustyink-theme-{}", process::id()));
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir)?;
+    }
+    fs::create_dir_all(&temp_dir)?;
+
+    let result = unpack(archive, kind, &temp_dir).and_then(|_| {
+        let entries = fs::read_dir(&temp_dir)?.collect::<Result<Vec<_>, _>>()?;
+        let top_dir = match entries.as_slice() {
+            [entry] if entry.path().is_dir() => Some(entry.path()),
+            _ => None,
+        };
+
+        let root = match (subdir, top_dir) {
+            // The folder is looked up from the root of the archive first
+            (Some(subdir), top_dir) => [Some(temp_dir.clone()), top_dir]
+                .into_iter()
+                .flatten()
+                .map(|x| x.join(subdir))
+                .find(|x| x.is_dir())
+                .with_context(|| {
+                    format!("Folder `{}` doesn't exist in the theme archive", subdir)
+                })?,
+            (None, Some(top_dir)) => top_dir,
+            (None, None) => temp_dir.clone(),
+        };
+
+        copy_dir(&root, project_dir)
+    });
+
+    fs::remove_dir_all(&temp_dir)?;
+
+    result
+}
+
+fn unpack(archive: &[u8], kind: ArchiveKind, dir: &Path) -> Result<()> {
+    match kind {
+        ArchiveKind::TarGz => tar::Archive::new(GzDecoder::new(archive))
+            .unpack(dir)
+            .context("Failed to extract theme archive"),
+        ArchiveKind::Zip => zip::ZipArchive::new(Cursor::new(archive))
+            .and_then(|mut x| x.extract(dir))
+            .context("Failed to extract theme archive"),
+    }
+}
+
+fn copy_dir(source: &Path, project_dir: &str) -> Result<()> {
+    let options = CopyOptions::new().content_only(true).overwrite(true);
+    fs_extra::dir::copy(source, project_dir, &options)
+        .with_context(|| format!("Failed to copy theme from {}", source.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use owo_colors::OwoColorize;
+
+use crate::shared::logger::Logger;
+
+pub const THEME: &str = "builtin";
+
+// Minimal theme and sample pages shipped with the binary, so projects can be
+// created without network access
+const FILES: [(&str, &str); 10] = [
+    ("theme/theme.toml", include_str!("starter/theme/theme.toml")),
+    ("theme/app.hbs", include_str!("starter/theme/app.hbs")),
+    ("theme/blog.hbs", include_str!("starter/theme/blog.hbs")),
+    ("theme/post.hbs", include_str!("starter/theme/post.hbs")),
+    ("theme/global.css", include_str!("starter/theme/global.css")),
+    ("pages/page.md", include_str!("starter/pages/page.md")),
+    (
+        "pages/about/page.md",
+        include_str!("starter/pages/about/page.md"),
+    ),
+    (
+        "pages/blog/page.md",
+        include_str!("starter/pages/blog/page.md"),
+    ),
+    (
+        "pages/blog/_defaults.yaml",
+        include_str!("starter/pages/blog/_defaults.yaml"),
+    ),
+    (
+        "pages/blog/hello-world.md",
+        include_str!("starter/pages/blog/hello-world.md"),
+    ),
+];
+
+pub fn write(project_dir: &str) -> Result<()> {
+    for (path, contents) in FILES {
+        let file_path = Path::new(project_dir).join(path);
+
+        let folder = file_path.parent().context("Failed to get parent folder")?;
+        fs::create_dir_all(folder)?;
+
+        Logger::new().activity(&format!("\tWriting file: {}", path.bold().green()));
+        fs::write(file_path, contents)?;
+    }
+
+    // The build expects a public folder, even an empty one
+    fs::create_dir_all(Path::new(project_dir).join("public"))?;
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
-use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use anyhow::Result;
 use axum::{
@@ -6,7 +10,7 @@ use axum::{
         ws::{Message, WebSocket, WebSocketUpgrade},
         State,
     },
-    response::IntoResponse,
+    response::{IntoResponse, Redirect},
     routing::get,
     Router,
 };
@@ -35,19 +39,32 @@ pub const WEBSOCKET_CLIENT_JS: &str = r#"
     </script>
 "#;
 
-pub async fn start(output_dir: String, port: u16, reload_tx: broadcast::Sender<()>) -> Result<()> {
+pub async fn start(
+    output_dir: String,
+    port: u16,
+    base_path: String,
+    reload_tx: broadcast::Sender<()>,
+) -> Result<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let listener = std::net::TcpListener::bind(addr)?;
     listener.set_nonblocking(true)?;
 
-    let app = Router::new()
-        .route("/__livereload", get(livereload_handler))
-        .nest_service("/", ServeDir::new(output_dir))
-        .with_state(reload_tx);
+    let app = Router::new().route("/__livereload", get(livereload_handler));
+
+    // Serve the site under its base path, the same way it is deployed
+    let app = if base_path.is_empty() {
+        app.nest_service("/", ServeDir::new(output_dir))
+    } else {
+        let index = format!("{}/", base_path);
+        app.route("/", get(move || async move { Redirect::temporary(&index) }))
+            .nest_service(&base_path, ServeDir::new(output_dir))
+    }
+    .with_state(reload_tx);
 
     Logger::new().success(&format!(
-        "Dev server started on -> http://localhost:{}",
-        port.blue()
+        "Dev server started on -> http://localhost:{}{}/",
+        port.blue(),
+        base_path
     ));
 
     axum::Server::from_tcp(listener)?
@@ -109,6 +126,18 @@ pub fn handle_file_changes(
         .watcher()
         .watch(input_dir.as_path(), RecursiveMode::Recursive)?;
 
+    // Parent themes usually live outside the site
+    let input_dir = input_dir.canonicalize()?;
+    for theme_dir in worker.get_theme_dirs() {
+        // Parent dirs are joined onto their child theme, like `theme/../base`
+        let theme_dir = Path::new(&theme_dir).canonicalize()?;
+        if !theme_dir.starts_with(&input_dir) {
+            debouncer
+                .watcher()
+                .watch(&theme_dir, RecursiveMode::Recursive)?;
+        }
+    }
+
     for result in rx {
         match result {
             Err(error) => log.error(&error.to_string()),
@@ -33,8 +33,8 @@ enum Commands {
 
         #[clap(
             required = false,
-            help = "Theme",
-            default_value = "pico",
+            help = "Theme name, folder, .tar.gz/.zip archive path or URL, or gh:owner/repo#ref:subdir",
+            default_value = create::DEFAULT_THEME,
             short = 't',
             long = "theme"
         )]
@@ -56,6 +56,12 @@ enum Commands {
         #[clap(required = true, help = "Input directory")]
         input_dir: PathBuf,
     },
+    /// Check the theme and front matter without building
+    #[command()]
+    Check {
+        #[clap(required = true, help = "Input directory")]
+        input_dir: PathBuf,
+    },
     /// Clean the site
     #[command()]
     Clean {},
@@ -97,6 +103,7 @@ async fn main() -> Result<()> {
             let worker = Worker::dev(&input_dir, Some(cache), true)?;
             let output_dir = worker.get_output_dir().to_string();
             let port = worker.get_settings().dev.port;
+            let base_path = worker.get_settings().get_build_settings().get_base_path();
 
             // Trigger a build
             if let Err(e) = worker.build() {
@@ -118,13 +125,26 @@ async fn main() -> Result<()> {
                 });
             }
 
-            dev::server::start(output_dir, port, reload_tx).await?;
+            dev::server::start(output_dir, port, base_path, reload_tx).await?;
         }
         Commands::Build { input_dir } => {
-            let worker = Worker::prod(&input_dir)?;
+            let worker = Worker::prod(&input_dir, Some(cache))?;
 
             if let Err(e) = worker.build() {
                 log.error(&format!("Build failed -> {}", e));
+                std::process::exit(1);
+            }
+        }
+        Commands::Check { input_dir } => {
+            let worker = Worker::prod(&input_dir, None)?;
+
+            log.activity("Checking site");
+            match worker.check() {
+                Ok(_) => log.success("No problems found"),
+                Err(e) => {
+                    log.error(&format!("Check failed -> {}", e));
+                    std::process::exit(1);
+                }
             }
         }
         Commands::Clean {} => {
@@ -19,6 +19,10 @@ impl Logger {
         println!("✔ {}", message.green());
     }
 
+    pub fn warn(&self, message: &str) {
+        println!("- Warning: {}", message.yellow().bold());
+    }
+
     pub fn error(&self, message: &str) {
         println!("- Error: {}", message.red().bold());
     }
@@ -5,8 +5,11 @@ use serde::{Deserialize, Serialize};
 pub struct Settings {
     pub dev: DevSettings,
     pub site: Option<SiteSettings>,
+    pub build: Option<BuildSettings>,
     pub meta: SiteMeta,
     pub navigation: NavigationSettings,
+    pub images: Option<ImageSettings>,
+    pub theme: Option<ThemeSettings>,
     pub data: Option<toml::Value>,
     pub remote_data: Option<toml::Value>,
 }
@@ -16,11 +19,13 @@ impl Settings {
         Self {
             dev: DevSettings { port: 3000 },
             site: None,
+            build: None,
             meta: SiteMeta {
                 title: "RustyInk".to_string(),
                 description: "A blazing fast static site generator".to_string(),
                 og_image_url: None,
-                base_url: None,
+                // Matches the dev server, so new projects build before being deployed
+                base_url: Some("http://localhost:3000".to_string()),
             },
             navigation: NavigationSettings {
                 links: Vec::<Link>::from([Link {
@@ -28,6 +33,8 @@ impl Settings {
                     url: "/".to_string(),
                 }]),
             },
+            images: None,
+            theme: None,
             data: None,
             remote_data: None,
         }
@@ -44,12 +51,57 @@ impl Settings {
             None => SiteSettings {
                 block_search_indexing: Some(false),
                 external_links_open_in_new_tab: Some(false),
+                container_callouts: Some(false),
+                summary_length: None,
+                words_per_minute: None,
                 script_urls: Some(Vec::<String>::new()),
                 style_urls: Some(Vec::<String>::new()),
             },
         }
     }
 
+    pub fn get_build_settings(&self) -> BuildSettings {
+        match &self.build {
+            Some(build) => build.clone(),
+            None => BuildSettings {
+                redirects_file: Some(false),
+                url_style: Some(UrlStyle::Pretty),
+                trailing_slash: Some(true),
+                base_path: None,
+                strict_templates: Some(false),
+            },
+        }
+    }
+
+    pub fn get_image_settings(&self) -> ImageSettings {
+        match &self.images {
+            Some(images) => images.clone(),
+            None => ImageSettings {
+                enabled: Some(false),
+                widths: None,
+                formats: None,
+                quality: None,
+                thumbnail_width: None,
+                strip_gps: Some(false),
+            },
+        }
+    }
+
+    // Absolute url of a url path that already includes the base path
+    pub fn get_permalink(&self, url: &str) -> Option<String> {
+        let base_url = self.meta.get_base_url()?;
+        let base_url = base_url.trim_end_matches('/');
+        let base_path = self.get_build_settings().get_base_path();
+
+        // `base_url` may or may not include the base path
+        let base_url = match base_url.strip_suffix(&base_path) {
+            Some(base_url) if !base_path.is_empty() => base_url,
+            _ => base_url,
+        };
+
+        Some(format!("{}{}", base_url, url))
+    }
+
     pub fn get_data_yaml(&self) -> Result<Option<serde_yaml::Value>> {
         if let Some(data) = &self.data {
             let data = serde_yaml::to_value(data)?;
@@ -60,6 +112,14 @@ impl Settings {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThemeSettings {
+    // Theme the site's theme falls back to, relative to the site
+    pub parent: Option<String>,
+    // Overrides the `params` of the theme manifest
+    pub params: Option<toml::Value>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DevSettings {
     pub port: u16,
@@ -69,6 +129,9 @@ pub struct DevSettings {
 pub struct SiteSettings {
     pub block_search_indexing: Option<bool>,
     pub external_links_open_in_new_tab: Option<bool>,
+    pub container_callouts: Option<bool>,
+    pub summary_length: Option<usize>,
+    pub words_per_minute: Option<usize>,
     pub script_urls: Option<Vec<String>>,
     pub style_urls: Option<Vec<String>>,
 }
@@ -82,6 +145,18 @@ impl SiteSettings {
         matches!(self.external_links_open_in_new_tab, Some(true))
     }
 
+    pub fn container_callouts(&self) -> bool {
+        matches!(self.container_callouts, Some(true))
+    }
+
+    pub fn get_summary_length(&self) -> usize {
+        self.summary_length.unwrap_or(70)
+    }
+
+    pub fn get_words_per_minute(&self) -> usize {
+        self.words_per_minute.unwrap_or(200).max(1)
+    }
+
     pub fn get_script_urls(&self) -> Vec<String> {
         match &self.script_urls {
             Some(urls) => urls.clone(),
@@ -97,6 +172,108 @@ impl SiteSettings {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildSettings {
+    pub redirects_file: Option<bool>,
+    pub url_style: Option<UrlStyle>,
+    pub trailing_slash: Option<bool>,
+    pub base_path: Option<String>,
+    pub strict_templates: Option<bool>,
+}
+
+impl BuildSettings {
+    pub fn has_redirects_file(&self) -> bool {
+        matches!(self.redirects_file, Some(true))
+    }
+
+    pub fn has_ugly_urls(&self) -> bool {
+        matches!(self.url_style, Some(UrlStyle::Ugly))
+    }
+
+    pub fn has_strict_templates(&self) -> bool {
+        matches!(self.strict_templates, Some(true))
+    }
+
+    pub fn has_trailing_slash(&self) -> bool {
+        !matches!(self.trailing_slash, Some(false))
+    }
+
+    // `/project` for sites served from a subdirectory, empty otherwise
+    pub fn get_base_path(&self) -> String {
+        match self.base_path.as_deref().map(|x| x.trim_matches('/')) {
+            Some(path) if !path.is_empty() => format!("/{}", path),
+            _ => String::new(),
+        }
+    }
+
+    // Prefixes a root relative url with the base path, unless it already has it
+    pub fn prefix_url(&self, url: &str) -> String {
+        let base_path = self.get_base_path();
+
+        if base_path.is_empty()
+            || !url.starts_with('/')
+            || url.starts_with("//")
+            || url == base_path
+            || url.starts_with(&format!("{}/", base_path))
+        {
+            return url.to_string();
+        }
+
+        format!("{}{}", base_path, url)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlStyle {
+    // `/about/` written to `about/index.html`
+    Pretty,
+    // `/about.html` written to `about.html`
+    Ugly,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSettings {
+    pub enabled: Option<bool>,
+    pub widths: Option<Vec<u32>>,
+    pub formats: Option<Vec<String>>,
+    pub quality: Option<u8>,
+    pub thumbnail_width: Option<u32>,
+    pub strip_gps: Option<bool>,
+}
+
+impl ImageSettings {
+    pub fn is_enabled(&self) -> bool {
+        matches!(self.enabled, Some(true))
+    }
+
+    pub fn get_widths(&self) -> Vec<u32> {
+        match &self.widths {
+            Some(widths) => widths.clone(),
+            None => vec![480, 960, 1440],
+        }
+    }
+
+    pub fn get_formats(&self) -> Vec<String> {
+        match &self.formats {
+            Some(formats) => formats.clone(),
+            None => vec![],
+        }
+    }
+
+    pub fn get_quality(&self) -> u8 {
+        self.quality.unwrap_or(80).clamp(1, 100)
+    }
+
+    pub fn get_thumbnail_width(&self) -> u32 {
+        self.thumbnail_width.unwrap_or(400).max(1)
+    }
+
+    pub fn is_gps_stripped(&self) -> bool {
+        matches!(self.strip_gps, Some(true))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SiteMeta {
     pub title: String,
@@ -1,4 +1,7 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use anyhow::{Context, Result};
 use serde_yaml::Value;
@@ -21,9 +24,12 @@ pub fn path_to_string(path: &PathBuf) -> Result<String> {
         })
 }
 
-pub fn parse_string_to_yaml(string: &str) -> Result<serde_yaml::Value> {
-    let metadata: serde_yaml::Value = serde_yaml::from_str(string)?;
-    Ok(metadata)
+// Path of a file of the site, like `pages/blog/launch.md`, used to name it in messages
+pub fn get_source_name(file: &str, input_dir: &str) -> String {
+    Path::new(file)
+        .strip_prefix(input_dir)
+        .map(|x| x.display().to_string())
+        .unwrap_or_else(|_| file.to_string())
 }
 
 pub fn merge_yaml_values(base: Value, other: Value) -> Value {